@@ -1,8 +1,9 @@
 #[macro_use]
 extern crate tera;
 
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
 fn to_extension_str(path: &PathBuf) -> &str {
@@ -27,7 +28,22 @@ fn main() {
         })
         .collect();
 
-    for shader in std::iter::once(&shaders_dir).chain(shaders.iter()) {
+    // traversals of the acceleration structures, every tracer template is rendered
+    // with each of them into its own shader
+    let accel_dir = shaders_dir.join("accel");
+    let mut snippets: Vec<_> = accel_dir
+        .read_dir()
+        .expect("can't read acceleration structures directory")
+        .map(|snippet_entry| snippet_entry.expect("can't read snippet").path())
+        .filter(|snippet| to_extension_str(&snippet) == "tera")
+        .collect();
+    snippets.sort();
+
+    for shader in std::iter::once(&shaders_dir)
+        .chain(shaders.iter())
+        .chain(std::iter::once(&accel_dir))
+        .chain(snippets.iter())
+    {
         println!(
             "cargo:rerun-if-changed={}",
             shader.to_str().expect("can't convert path to str")
@@ -45,23 +61,98 @@ fn main() {
     let tera = compile_templates!(&templates_glob);
     let rendered_shaders_dir = root_dir.join("target").join("shaders");
     std::fs::create_dir_all(rendered_shaders_dir.clone()).expect("failed to create directory");
+
+    // snippet of accel/grid.glsl.tera is rendered into the tracers as the "grid" traversal
+    let traversals: Vec<_> = snippets
+        .iter()
+        .map(|snippet| {
+            let name = snippet
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .trim_right_matches(".glsl.tera")
+                .to_string();
+            let template = snippet
+                .strip_prefix(&shaders_dir)
+                .unwrap()
+                .to_str()
+                .unwrap();
+            let rendered_snippet = tera
+                .render(template, &tera::Context::new())
+                .expect("failed to render traversal snippet");
+            (name, rendered_snippet)
+        })
+        .collect();
+
+    // base templates like tracer.comp.tera aren't shaders on their own,
+    // they are only rendered through the templates extending them
+    let base_templates: HashSet<_> = shaders
+        .iter()
+        .filter(|shader| to_extension_str(&shader) == "tera")
+        .filter_map(|shader| {
+            let mut source = String::new();
+            File::open(shader)
+                .and_then(|mut file| file.read_to_string(&mut source))
+                .expect("can't read shader template");
+            let prefix = "{% extends \"";
+            let source = source.trim_left();
+            if !source.starts_with(prefix) {
+                return None;
+            }
+            let base = &source[prefix.len()..];
+            base.find('"').map(|end| shaders_dir.join(&base[..end]))
+        })
+        .collect();
+
     for shader in shaders
         .into_iter()
-        .filter(|shader| to_extension_str(&shader) == "tera")
+        .filter(|shader| to_extension_str(&shader) == "tera" && !base_templates.contains(shader))
     {
-        let rendered_shader = tera.render(
-            shader.strip_prefix(&shaders_dir).unwrap().to_str().unwrap(),
-            &tera::Context::new(),
-        ).expect("failed to render shader template");
-        let output_path = rendered_shaders_dir.join(
-            shader
-                .strip_prefix(&shaders_dir)
-                .unwrap()
-                .with_extension(""),
+        let template = shader.strip_prefix(&shaders_dir).unwrap();
+        // e.g. raycasting and comp for raycasting.comp.tera
+        let shader_name = template.with_extension("");
+        let stem = shader_name.file_stem().unwrap().to_str().unwrap();
+        let extension = to_extension_str(&shader_name);
+
+        // modules of the shaders, which are included by the tracers, see tracers::raycasting
+        let mut modules = String::from(
+            "// generated by build.rs, a shader for every traversal in shaders/accel\n\
+             pub mod traversals {\n",
         );
-        println!("{:?}", output_path);
-        let mut file = File::create(output_path).expect("can't create file for rendered shader");
-        file.write_all(rendered_shader.as_bytes())
-            .expect("failed to write rendered shader to file");
+        for &(ref traversal, ref rendered_snippet) in &traversals {
+            let mut context = tera::Context::new();
+            context.add("traversal", rendered_snippet);
+            let rendered_shader = tera
+                .render(template.to_str().unwrap(), &context)
+                .expect("failed to render shader template");
+            let file_name = format!("{}_{}.{}", stem, traversal, extension);
+            let output_path = rendered_shaders_dir.join(&file_name);
+            println!("{:?}", output_path);
+            let mut file =
+                File::create(output_path).expect("can't create file for rendered shader");
+            file.write_all(rendered_shader.as_bytes())
+                .expect("failed to write rendered shader to file");
+
+            modules.push_str(&format!(
+                r#"    pub mod {} {{
+        #![allow(dead_code)]
+        #[derive(VulkanoShader)]
+        #[ty = "compute"]
+        #[path = "target/shaders/{}"]
+        struct Dummy;
+    }}
+"#,
+                traversal, file_name
+            ));
+        }
+        modules.push_str("}\n");
+        let names: Vec<_> = traversals.iter().map(|t| t.0.as_str()).collect();
+        modules.push_str(&format!("traversal_pipelines!({});\n", names.join(", ")));
+
+        let mut file = File::create(rendered_shaders_dir.join(format!("{}_traversals.rs", stem)))
+            .expect("can't create file for shader modules");
+        file.write_all(modules.as_bytes())
+            .expect("failed to write shader modules to file");
     }
 }
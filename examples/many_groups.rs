@@ -0,0 +1,109 @@
+//! Generates a Wavefront model with thousands of groups to benchmark material lookup:
+//!
+//! ```bash
+//! cargo run --release --example many_groups -- /tmp/many_groups.obj 4096
+//! cargo run --release -- --benchmark /tmp/many_groups.obj
+//! ```
+//!
+//! Every group is a separate cube with its own material, the cubes are laid out
+//! on a square plane facing the default camera.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const DEFAULT_GROUP_COUNT: usize = 4096;
+const MATERIAL_COUNT: usize = 16;
+
+const CUBE_POSITIONS: [[f32; 3]; 8] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 0.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [1.0, 0.0, 1.0],
+    [1.0, 1.0, 1.0],
+    [0.0, 1.0, 1.0],
+];
+
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 3, 2, 1],
+    [4, 5, 6, 7],
+    [0, 1, 5, 4],
+    [3, 7, 6, 2],
+    [0, 4, 7, 3],
+    [1, 2, 6, 5],
+];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <output.obj> [group count]", args[0]);
+        std::process::exit(1);
+    }
+    let path = Path::new(&args[1]);
+    let group_count = args.get(2)
+        .map(|count| count.parse().expect("group count must be a number"))
+        .unwrap_or(DEFAULT_GROUP_COUNT);
+
+    write_materials(&path.with_extension("mtl")).expect("failed to write materials");
+    write_model(path, group_count).expect("failed to write model");
+    println!(
+        "{} groups, {} triangles written to {}",
+        group_count,
+        12 * group_count,
+        path.display()
+    );
+}
+
+fn write_materials(path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for i in 0..MATERIAL_COUNT {
+        let hue = i as f32 / MATERIAL_COUNT as f32;
+        writeln!(writer, "newmtl material{}", i)?;
+        writeln!(writer, "Kd {} {} {}", hue, 1.0 - hue, 0.5)?;
+        writeln!(writer, "Ks 0.2 0.2 0.2")?;
+        writeln!(writer, "Ns 32")?;
+    }
+    Ok(())
+}
+
+fn write_model(path: &Path, group_count: usize) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mtl_name = path.with_extension("mtl");
+    let mtl_name = mtl_name.file_name().unwrap().to_string_lossy();
+    writeln!(writer, "mtllib {}", mtl_name)?;
+
+    let side = (group_count as f32).sqrt().ceil() as usize;
+    for group in 0..group_count {
+        let offset = [
+            1.5 * (group % side) as f32 - 0.75 * side as f32,
+            1.5 * (group / side) as f32 - 0.75 * side as f32,
+            -2.0 * side as f32,
+        ];
+        for p in &CUBE_POSITIONS {
+            writeln!(
+                writer,
+                "v {} {} {}",
+                p[0] + offset[0],
+                p[1] + offset[1],
+                p[2] + offset[2]
+            )?;
+        }
+        writeln!(writer, "g cube{}", group)?;
+        writeln!(writer, "usemtl material{}", group % MATERIAL_COUNT)?;
+        let first = 8 * group + 1;
+        for face in &CUBE_FACES {
+            writeln!(
+                writer,
+                "f {} {} {} {}",
+                first + face[0],
+                first + face[1],
+                first + face[2],
+                first + face[3]
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+extern crate vulkano;
+
+use bvh::{LbvhStructure, SahBvhStructure};
+use grid::{GridKind, GridStructure};
+use scene::ModelBuffers;
+use tracers;
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Structure the scene is traversed with, chosen at startup
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccelerationKind {
+    Grid(GridKind),
+    /// Linear BVH built on the GPU
+    Lbvh,
+    /// BVH built with the surface area heuristic on the CPU
+    SahBvh,
+}
+
+/// Structure the tracers intersect the scene with. Its traversal is a snippet in shaders/accel,
+/// which build.rs renders into its own tracer, so a new structure only adds its snippet
+/// and implements this trait.
+pub trait AccelerationStructure {
+    /// Shown in the statistics
+    fn name(&self) -> &'static str;
+
+    /// Builds the structure for the current scene after `future`, it can be traversed
+    /// after the returned future
+    fn build(&mut self, future: Box<vulkano::sync::GpuFuture>) -> Box<vulkano::sync::GpuFuture>;
+
+    /// Name of the snippet, which traverses the structure in the tracers,
+    /// e.g. "grid" for shaders/accel/grid.glsl.tera
+    fn traversal(&self) -> &'static str;
+
+    /// Descriptor set with the buffers in the order of the bindings of the snippet.
+    /// The snippet declares everything in its own set, so parameters of the structure,
+    /// like the grid resolution, are its own uniform buffers bound in this set as well.
+    fn build_descriptor_set(
+        &self,
+        pipeline: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
+        set_id: usize,
+    ) -> Result<
+        Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+        vulkano::descriptor::descriptor_set::PersistentDescriptorSetError,
+    >;
+
+    /// Writes the size of the structure and how many times its parts were intersected
+    fn write_statistics(
+        &self,
+        out: &mut fmt::Write,
+        statistics: &tracers::ty::Statistics,
+        primary_rays: u32,
+    ) -> fmt::Result;
+}
+
+pub fn new_structure(
+    queue: Arc<vulkano::device::Queue>,
+    scene_buffers: &ModelBuffers,
+    kind: AccelerationKind,
+) -> Box<AccelerationStructure> {
+    match kind {
+        AccelerationKind::Grid(grid_kind) => {
+            Box::new(GridStructure::new(queue, scene_buffers, grid_kind))
+        }
+        AccelerationKind::Lbvh => Box::new(LbvhStructure::new(queue, scene_buffers)),
+        AccelerationKind::SahBvh => Box::new(SahBvhStructure::new(queue, scene_buffers)),
+    }
+}
@@ -2,21 +2,28 @@ use cgmath;
 use clap;
 use vulkano;
 
+use accel::AccelerationKind;
+use grid::GridKind;
+use scene::{EnvironmentDescription, NormalsMode, SceneDescription, UpAxis};
+
 use std;
 
 pub struct Args {
-    pub model: String,
+    pub scene: SceneDescription,
     pub resolution: Vec<u32>,
     pub position: cgmath::Vector3<f32>,
     pub fov: [f32; 2],
+    pub yaw: f32,
+    pub pitch: f32,
     pub sensitivity: f32,
     pub fps_update_interval: i64,
     pub log_level: vulkano::instance::debug::MessageTypes,
     pub benchmark: bool,
+    pub acceleration: AccelerationKind,
 }
 
 fn is_supported_model_format(val: String) -> Result<(), String> {
-    const SUPPORTED_FORMATS: &[&str] = &["obj"];
+    const SUPPORTED_FORMATS: &[&str] = &["obj", "gltf", "glb", "ply", "stl", "toml"];
     let extension = val.rsplit('.')
         .next()
         .ok_or("can't determinate extension of file")?;
@@ -59,7 +66,7 @@ impl Args {
             .about("Interactive raytracer, that renders triangulated models")
             .arg(
                 clap::Arg::with_name("model")
-                    .help("Sets the path to file with model to render")
+                    .help("Sets the path to file with model or scene description (.toml) to render")
                     .required(true)
                     .index(1)
                     .validator(is_supported_model_format),
@@ -90,18 +97,57 @@ impl Args {
                     .display_order(3)
                     .help("Sets the field of view [default: 40.0 40.0]"),
             )
+            .arg(
+                clap::Arg::with_name("default-color")
+                    .long("default-color")
+                    .number_of_values(3)
+                    .value_names(&["r", "g", "b"])
+                    .display_order(4)
+                    .help("Sets the color of meshes without material [default: 0.8 0.8 0.8]"),
+            )
+            .arg(
+                clap::Arg::with_name("normals")
+                    .long("normals")
+                    .takes_value(true)
+                    .possible_values(&["flat", "smooth"])
+                    .display_order(5)
+                    .help("Sets how to generate normals missing in the model [default: smooth]"),
+            )
+            .arg(
+                clap::Arg::with_name("crease-angle")
+                    .long("crease-angle")
+                    .takes_value(true)
+                    .display_order(6)
+                    .help(
+                        "Sets the maximum angle (in degrees) between faces, \
+                         which normals are smoothed together [default: 60.0]",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("texture-path")
+                    .long("texture-path")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("dir")
+                    .display_order(7)
+                    .help(
+                        "Adds the directory, where textures are searched \
+                         if they are not found next to the model",
+                    ),
+            )
             .arg(
                 clap::Arg::with_name("sensitivity")
                     .long("sensitivity")
                     .takes_value(true)
-                    .display_order(4)
+                    .display_order(8)
                     .help("Sets the sensitivity of the controls (camera movement) [default: 1.0]"),
             )
             .arg(
                 clap::Arg::with_name("fps-update-interval")
                     .long("fps-update-interval")
                     .takes_value(true)
-                    .display_order(5)
+                    .display_order(9)
                     .help(
                         "Sets the interval (in milliseconds) of FPS update. \
                          Displayed FPS is the average in the last interval [default: 100]",
@@ -112,33 +158,213 @@ impl Args {
                     .long("log-level")
                     .takes_value(true)
                     .possible_values(LOG_LEVELS)
-                    .display_order(6)
+                    .display_order(10)
                     .help("Sets the log messages amount [default: perf]"),
             )
+            .arg(
+                clap::Arg::with_name("anisotropy")
+                    .long("anisotropy")
+                    .takes_value(true)
+                    .display_order(14)
+                    .help(
+                        "Sets the maximum anisotropy of texture filtering, \
+                         1.0 turns it off [default: 1.0]",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("environment")
+                    .long("environment")
+                    .takes_value(true)
+                    .value_name("file")
+                    .display_order(15)
+                    .help("Sets the equirectangular .hdr or .exr image surrounding the scene"),
+            )
+            .arg(
+                clap::Arg::with_name("environment-rotation")
+                    .long("environment-rotation")
+                    .takes_value(true)
+                    .display_order(16)
+                    .help(
+                        "Sets the rotation (in degrees) of the environment \
+                         around the vertical axis [default: 0.0]",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("environment-intensity")
+                    .long("environment-intensity")
+                    .takes_value(true)
+                    .display_order(17)
+                    .help("Sets the brightness multiplier of the environment [default: 1.0]"),
+            )
+            .arg(
+                clap::Arg::with_name("rebuild-cache")
+                    .long("rebuild-cache")
+                    .display_order(11)
+                    .help("Ignore cached models and load them from the model files again"),
+            )
+            .arg(
+                clap::Arg::with_name("repair")
+                    .long("repair")
+                    .display_order(12)
+                    .help(
+                        "Drop degenerate triangles, weld duplicate vertices \
+                         and drop unused vertices of the models",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("up-axis")
+                    .long("up-axis")
+                    .takes_value(true)
+                    .possible_values(&["y", "z"])
+                    .display_order(18)
+                    .help(
+                        "Sets the axis pointing up in the models, \
+                         Z-up models are rotated to Y-up [default: y]",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("unit-scale")
+                    .long("unit-scale")
+                    .takes_value(true)
+                    .display_order(19)
+                    .help(
+                        "Sets the scale converting units of the models, \
+                         e.g. 0.001 for millimetres [default: 1.0]",
+                    ),
+            )
+            .arg(
+                clap::Arg::with_name("recenter")
+                    .long("recenter")
+                    .display_order(20)
+                    .help("Move the centers of the models to the origin"),
+            )
+            .arg(
+                clap::Arg::with_name("accel")
+                    .long("accel")
+                    .takes_value(true)
+                    .possible_values(&["grid", "two-level-grid", "lbvh", "sah-bvh"])
+                    .display_order(21)
+                    .help(
+                        "Sets the acceleration structure: two-level grid is better for small \
+                         detailed objects in a large empty space, linear BVH is built on the \
+                         GPU, SAH BVH is built once on the CPU [default: grid]",
+                    ),
+            )
             .arg(
                 clap::Arg::with_name("benchmark")
                     .long("benchmark")
-                    .display_order(7)
+                    .display_order(13)
                     .help("Turn on benchmarking"),
             )
             .get_matches();
-        let model = matches.value_of("model").unwrap().to_string();
+        let model = std::path::Path::new(matches.value_of("model").unwrap());
+        let mut scene = if model.extension().map_or(false, |e| e == "toml") {
+            SceneDescription::from_file(model).unwrap_or_else(|e| {
+                let message = e.to_string();
+                clap::Error::with_description(&message, clap::ErrorKind::InvalidValue).exit()
+            })
+        } else {
+            SceneDescription::from_model(model)
+        };
         // kbknapp promisses `default_values` method in clap v3. But for now...
+        // Command line options take precedence over the scene description
         let resolution = if matches.is_present("resolution") {
             values_t!(matches, "resolution", u32).unwrap_or_else(|e| e.exit())
         } else {
-            vec![640, 480]
+            scene.render.resolution.unwrap_or([640, 480]).to_vec()
         };
         let position = if matches.is_present("position") {
             values_t!(matches, "position", f32).unwrap_or_else(|e| e.exit())
         } else {
-            vec![0.0, 0.0, 5.0]
+            scene.camera.position.unwrap_or([0.0, 0.0, 5.0]).to_vec()
         };
         let fov = if matches.is_present("fov") {
             values_t!(matches, "fov", f32).unwrap_or_else(|e| e.exit())
         } else {
-            vec![40.0, 40.0]
+            scene.camera.fov.unwrap_or([40.0, 40.0]).to_vec()
         };
+        if matches.is_present("default-color") {
+            let color = values_t!(matches, "default-color", f32).unwrap_or_else(|e| e.exit());
+            scene.render.default_color = Some([color[0], color[1], color[2]]);
+        }
+        if matches.is_present("normals") {
+            let normals = match matches.value_of("normals").unwrap() {
+                "flat" => NormalsMode::Flat,
+                _ => NormalsMode::Smooth,
+            };
+            for model in &mut scene.models {
+                model.normals = normals;
+            }
+        }
+        if matches.is_present("crease-angle") {
+            let crease_angle = value_t!(matches, "crease-angle", f32).unwrap_or_else(|e| e.exit());
+            for model in &mut scene.models {
+                model.crease_angle = crease_angle;
+            }
+        }
+        if let Some(paths) = matches.values_of("texture-path") {
+            scene
+                .texture_paths
+                .extend(paths.map(std::path::PathBuf::from));
+        }
+        if matches.is_present("anisotropy") {
+            let anisotropy = value_t!(matches, "anisotropy", f32).unwrap_or_else(|e| e.exit());
+            scene.render.anisotropy = Some(anisotropy);
+        }
+        if let Some(path) = matches.value_of("environment") {
+            let path = std::path::PathBuf::from(path);
+            match scene.environment {
+                Some(ref mut environment) => environment.path = path,
+                None => scene.environment = Some(EnvironmentDescription::new(path)),
+            }
+        }
+        if matches.is_present("environment-rotation") || matches.is_present("environment-intensity")
+        {
+            let environment = scene.environment.as_mut().unwrap_or_else(|| {
+                clap::Error::with_description(
+                    "environment rotation and intensity require an environment map",
+                    clap::ErrorKind::MissingRequiredArgument,
+                ).exit()
+            });
+            if matches.is_present("environment-rotation") {
+                environment.rotation =
+                    value_t!(matches, "environment-rotation", f32).unwrap_or_else(|e| e.exit());
+            }
+            if matches.is_present("environment-intensity") {
+                environment.intensity =
+                    value_t!(matches, "environment-intensity", f32).unwrap_or_else(|e| e.exit());
+            }
+        }
+        if matches.is_present("rebuild-cache") {
+            scene.cache.rebuild = true;
+        }
+        if matches.is_present("repair") {
+            for model in &mut scene.models {
+                model.repair = true;
+            }
+        }
+        if matches.is_present("up-axis") {
+            let up_axis = match matches.value_of("up-axis").unwrap() {
+                "z" => UpAxis::Z,
+                _ => UpAxis::Y,
+            };
+            for model in &mut scene.models {
+                model.up_axis = up_axis;
+            }
+        }
+        if matches.is_present("unit-scale") {
+            let unit_scale = value_t!(matches, "unit-scale", f32).unwrap_or_else(|e| e.exit());
+            for model in &mut scene.models {
+                model.unit_scale = unit_scale;
+            }
+        }
+        if matches.is_present("recenter") {
+            for model in &mut scene.models {
+                model.recenter = true;
+            }
+        }
+        let yaw = scene.camera.yaw;
+        let pitch = scene.camera.pitch;
         // ...and if I use `default_value` for this one, it will always dispaly it
         // in the help message if no model is passed.
         // which is not the end of the world but just pisses me off
@@ -158,15 +384,24 @@ impl Args {
             vulkano::instance::debug::MessageTypes::errors_and_warnings()
         };
         let benchmark = matches.is_present("benchmark");
+        let acceleration = match matches.value_of("accel") {
+            Some("two-level-grid") => AccelerationKind::Grid(GridKind::TwoLevel),
+            Some("lbvh") => AccelerationKind::Lbvh,
+            Some("sah-bvh") => AccelerationKind::SahBvh,
+            _ => AccelerationKind::Grid(GridKind::Uniform),
+        };
         Args {
-            model,
+            scene,
             resolution,
             position: cgmath::Vector3::new(position[0], position[1], position[2]),
             fov: [fov[0], fov[1]],
+            yaw,
+            pitch,
             sensitivity,
             fps_update_interval,
             log_level,
             benchmark,
+            acceleration,
         }
     }
 }
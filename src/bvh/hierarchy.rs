@@ -0,0 +1,168 @@
+extern crate vulkano;
+
+use std::iter;
+use std::sync::Arc;
+
+mod lbvh_hierarchy {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/lbvh_hierarchy.comp"]
+    struct Dummy;
+}
+
+mod lbvh_refit {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/lbvh_refit.comp"]
+    struct Dummy;
+}
+
+/// Emits the nodes of the hierarchy over the sorted Morton codes
+/// and then propagates the leaf bounds to the root
+pub struct HierarchyEmitter {
+    hierarchy_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    hierarchy_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<lbvh_hierarchy::Layout>,
+            >,
+        >,
+    >,
+    refit_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    refit_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<lbvh_refit::Layout>,
+            >,
+        >,
+    >,
+    leaf_count: usize,
+    work_groups_count: u32,
+}
+
+impl HierarchyEmitter {
+    pub fn new(queue: Arc<vulkano::device::Queue>, leaf_count: usize) -> HierarchyEmitter {
+        let device = queue.device();
+
+        let hierarchy_pipeline = Arc::new({
+            let shader = lbvh_hierarchy::Shader::load(device.clone())
+                .expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+        let refit_pipeline = Arc::new({
+            let shader =
+                lbvh_refit::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let hierarchy_ds_pool =
+            vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+                hierarchy_pipeline.clone(),
+                0,
+            );
+        let refit_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            refit_pipeline.clone(),
+            0,
+        );
+
+        HierarchyEmitter {
+            hierarchy_pipeline,
+            hierarchy_ds_pool,
+            refit_pipeline,
+            refit_ds_pool,
+            leaf_count,
+            work_groups_count: super::work_groups_count(leaf_count),
+        }
+    }
+
+    /// Returns the buffer with `2 * leaf_count - 1` nodes, the root is the first one
+    pub fn emit_hierarchy(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        queue: Arc<vulkano::device::Queue>,
+        object_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        keys: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        values: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) -> (
+        vulkano::command_buffer::AutoCommandBufferBuilder,
+        Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) {
+        let device = queue.device();
+        let node_count = 2 * self.leaf_count - 1;
+
+        let nodes_buffer = vulkano::buffer::DeviceLocalBuffer::<[lbvh_hierarchy::ty::Node]>::array(
+            device.clone(),
+            node_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create nodes buffer");
+        let parents_buffer = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            node_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create parents buffer");
+        // single leaf has no internal nodes, but the buffer can't be empty
+        let visits_buffer = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            self.leaf_count.max(2) - 1,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create visits buffer");
+
+        let hierarchy_ds = self.hierarchy_ds_pool
+            .next()
+            .add_buffer(object_bounds)
+            .unwrap()
+            .add_buffer(keys)
+            .unwrap()
+            .add_buffer(values)
+            .unwrap()
+            .add_buffer(nodes_buffer.clone())
+            .unwrap()
+            .add_buffer(parents_buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let refit_ds = self.refit_ds_pool
+            .next()
+            .add_buffer(parents_buffer)
+            .unwrap()
+            .add_buffer(visits_buffer.clone())
+            .unwrap()
+            .add_buffer(nodes_buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let builder = builder
+            .dispatch(
+                [self.work_groups_count, 1, 1],
+                self.hierarchy_pipeline.clone(),
+                hierarchy_ds,
+                (),
+            )
+            .unwrap()
+            .fill_buffer(visits_buffer, 0)
+            .unwrap()
+            .dispatch(
+                [self.work_groups_count, 1, 1],
+                self.refit_pipeline.clone(),
+                refit_ds,
+                (),
+            )
+            .unwrap();
+        (builder, nodes_buffer)
+    }
+}
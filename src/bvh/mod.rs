@@ -0,0 +1,337 @@
+extern crate vulkano;
+
+mod hierarchy;
+mod morton_codes;
+mod object_bounds;
+mod radix_sort;
+mod sah;
+use self::hierarchy::HierarchyEmitter;
+use self::morton_codes::MortonCoder;
+use self::object_bounds::ObjectBoundsFinder;
+use self::radix_sort::RadixSorter;
+use self::sah::SahBvh;
+
+use vulkano::sync::GpuFuture;
+
+use accel::AccelerationStructure;
+use gl_types::Vec3;
+use scene::ModelBuffers;
+use tracers;
+
+use std::cmp;
+use std::fmt;
+use std::iter;
+use std::sync::Arc;
+
+const WORKGROUP_SIZE: usize = 256;
+/// Size of the traversal stack in `intersect_structure`, must match `accel/bvh.glsl.tera`.
+/// The stack holds one node more than the depth of the tree.
+const STACK_SIZE: usize = 64;
+// must match the codes of lbvh_morton.comp, 10 bits for every axis
+const MORTON_BITS: usize = 30;
+
+/// Bounding volume hierarchy over the triangles and bounded primitives, the root is the first
+/// node. Leaves reference a range of `references_buffer`, which contains the object indices.
+pub struct Bvh {
+    pub node_count: usize,
+    pub nodes_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub references_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+}
+
+/// Builds the linear BVH on the GPU: objects are sorted by Morton codes of their centroids
+/// and the hierarchy is emitted from the sorted codes (Karras, 2012).
+pub struct LbvhBuilder {
+    queue: Arc<vulkano::device::Queue>,
+    object_bounds_finder: ObjectBoundsFinder,
+    morton_coder: MortonCoder,
+    radix_sorter: RadixSorter,
+    hierarchy_emitter: HierarchyEmitter,
+    /// Triangles and bounded primitives, which are referenced by the leaves
+    object_count: usize,
+}
+
+impl LbvhBuilder {
+    pub fn new(queue: Arc<vulkano::device::Queue>, scene_buffers: &ModelBuffers) -> LbvhBuilder {
+        let triangle_count = scene_buffers.triangle_count;
+        let object_count = triangle_count + scene_buffers.primitive_count;
+        // every level splits the objects by a bit of their codes, or by a bit of their
+        // indices for the equal codes, so the depth is limited by the number of bits
+        let index_bits = 32 - (cmp::max(object_count, 1) as u32 - 1).leading_zeros() as usize;
+        assert!(
+            MORTON_BITS + index_bits < STACK_SIZE,
+            "LBVH over {} objects may not fit the traversal stack",
+            object_count
+        );
+        let object_bounds_finder = ObjectBoundsFinder::new(
+            queue.clone(),
+            scene_buffers.positions.clone(),
+            scene_buffers.indices.clone(),
+            scene_buffers.primitive_bounds.clone(),
+            triangle_count,
+            scene_buffers.primitive_count,
+        );
+        let morton_coder = MortonCoder::new(queue.clone(), object_count);
+        let radix_sorter = RadixSorter::new(queue.clone(), object_count);
+        let hierarchy_emitter = HierarchyEmitter::new(queue.clone(), object_count);
+        LbvhBuilder {
+            queue,
+            object_bounds_finder,
+            morton_coder,
+            radix_sorter,
+            hierarchy_emitter,
+            object_count,
+        }
+    }
+
+    /// All passes are recorded into a single command buffer, nothing is read back
+    pub fn build(
+        &mut self,
+        future: Box<vulkano::sync::GpuFuture>,
+    ) -> (Bvh, Box<vulkano::sync::GpuFuture>) {
+        // the passes can't run over empty buffers, the empty hierarchy is a leaf
+        // without references, the same as built by the SAH builder
+        if self.object_count == 0 {
+            return (SahBvh::new(&[]).upload(self.queue.clone()), future);
+        }
+        let device = self.queue.device();
+
+        let object_bounds = vulkano::buffer::DeviceLocalBuffer::<[Vec3]>::array(
+            device.clone(),
+            2 * self.object_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(self.queue.family()),
+        ).expect("failed to create object bounds buffer");
+        let centroid_bounds = vulkano::buffer::DeviceLocalBuffer::<[u32; 6]>::new(
+            device.clone(),
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(self.queue.family()),
+        ).expect("failed to create centroid bounds buffer");
+        let keys = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            self.object_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(self.queue.family()),
+        ).expect("failed to create keys buffer");
+        let values = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            self.object_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(self.queue.family()),
+        ).expect("failed to create values buffer");
+
+        // empty bounds in the encoding of the shaders: maximal minimum and minimal maximum
+        let builder = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
+            device.clone(),
+            self.queue.family(),
+        ).unwrap()
+            .update_buffer(centroid_bounds.clone(), [!0, !0, !0, 0, 0, 0])
+            .unwrap();
+        let builder = self.object_bounds_finder.find_bounds(
+            builder,
+            object_bounds.clone(),
+            centroid_bounds.clone(),
+        );
+        let builder = self.morton_coder.calculate_codes(
+            builder,
+            object_bounds.clone(),
+            centroid_bounds,
+            keys.clone(),
+            values.clone(),
+        );
+        let builder =
+            self.radix_sorter
+                .sort(builder, self.queue.clone(), keys.clone(), values.clone());
+        let (builder, nodes_buffer) = self.hierarchy_emitter.emit_hierarchy(
+            builder,
+            self.queue.clone(),
+            object_bounds,
+            keys,
+            values.clone(),
+        );
+        let command_buffer = builder.build().unwrap();
+
+        let future = future
+            .then_execute(self.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+
+        (
+            Bvh {
+                node_count: 2 * self.object_count - 1,
+                nodes_buffer,
+                references_buffer: values,
+            },
+            Box::new(future),
+        )
+    }
+}
+
+impl Bvh {
+    /// Nodes and references in the bindings of accel/bvh.glsl.tera
+    fn build_descriptor_set(
+        &self,
+        pipeline: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
+        set_id: usize,
+    ) -> Result<
+        Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+        vulkano::descriptor::descriptor_set::PersistentDescriptorSetError,
+    > {
+        let ds = vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+            pipeline,
+            set_id,
+        ).add_buffer(self.nodes_buffer.clone())?
+            .add_buffer(self.references_buffer.clone())?
+            .build()
+            .expect("failed to build BVH descriptor set");
+        Ok(Arc::new(ds))
+    }
+
+    fn write_statistics(
+        &self,
+        out: &mut fmt::Write,
+        statistics: &tracers::ty::Statistics,
+        primary_rays: u32,
+    ) -> fmt::Result {
+        writeln!(out, "\tnode count: {}", self.node_count)?;
+        writeln!(out, "\tnode intersections: {}", statistics.node_intersections)?;
+        writeln!(
+            out,
+            "\tintersections per ray: {}",
+            statistics.node_intersections as f32 / primary_rays as f32
+        )?;
+        writeln!(
+            out,
+            "\tintersections per node: {}",
+            statistics.node_intersections as f32 / self.node_count as f32
+        )
+    }
+}
+
+/// Linear BVH built on the GPU, rebuilt when the scene is reloaded
+pub struct LbvhStructure {
+    builder: LbvhBuilder,
+    /// `None` until the first build
+    bvh: Option<Bvh>,
+}
+
+impl LbvhStructure {
+    pub fn new(queue: Arc<vulkano::device::Queue>, scene_buffers: &ModelBuffers) -> LbvhStructure {
+        LbvhStructure {
+            builder: LbvhBuilder::new(queue, scene_buffers),
+            bvh: None,
+        }
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.as_ref().expect("BVH isn't built")
+    }
+}
+
+impl AccelerationStructure for LbvhStructure {
+    fn name(&self) -> &'static str {
+        "LBVH"
+    }
+
+    fn build(&mut self, future: Box<vulkano::sync::GpuFuture>) -> Box<vulkano::sync::GpuFuture> {
+        let (bvh, future) = self.builder.build(future);
+        self.bvh = Some(bvh);
+        future
+    }
+
+    fn traversal(&self) -> &'static str {
+        "bvh"
+    }
+
+    fn build_descriptor_set(
+        &self,
+        pipeline: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
+        set_id: usize,
+    ) -> Result<
+        Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+        vulkano::descriptor::descriptor_set::PersistentDescriptorSetError,
+    > {
+        self.bvh().build_descriptor_set(pipeline, set_id)
+    }
+
+    fn write_statistics(
+        &self,
+        out: &mut fmt::Write,
+        statistics: &tracers::ty::Statistics,
+        primary_rays: u32,
+    ) -> fmt::Result {
+        self.bvh().write_statistics(out, statistics, primary_rays)
+    }
+}
+
+/// BVH built with the surface area heuristic on the CPU and uploaded to the GPU
+pub struct SahBvhStructure {
+    queue: Arc<vulkano::device::Queue>,
+    object_bounds: Vec<sah::Bounds>,
+    /// `None` until the first build
+    bvh: Option<Bvh>,
+}
+
+impl SahBvhStructure {
+    pub fn new(
+        queue: Arc<vulkano::device::Queue>,
+        scene_buffers: &ModelBuffers,
+    ) -> SahBvhStructure {
+        SahBvhStructure {
+            queue,
+            object_bounds: scene_buffers.object_bounds.clone(),
+            bvh: None,
+        }
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.as_ref().expect("BVH isn't built")
+    }
+}
+
+impl AccelerationStructure for SahBvhStructure {
+    fn name(&self) -> &'static str {
+        "SAH BVH"
+    }
+
+    /// Builds the hierarchy on the CPU, the upload is finished before it returns
+    fn build(&mut self, future: Box<vulkano::sync::GpuFuture>) -> Box<vulkano::sync::GpuFuture> {
+        let bvh = SahBvh::new(&self.object_bounds).upload(self.queue.clone());
+        self.bvh = Some(bvh);
+        future
+    }
+
+    fn traversal(&self) -> &'static str {
+        "bvh"
+    }
+
+    fn build_descriptor_set(
+        &self,
+        pipeline: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
+        set_id: usize,
+    ) -> Result<
+        Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+        vulkano::descriptor::descriptor_set::PersistentDescriptorSetError,
+    > {
+        self.bvh().build_descriptor_set(pipeline, set_id)
+    }
+
+    fn write_statistics(
+        &self,
+        out: &mut fmt::Write,
+        statistics: &tracers::ty::Statistics,
+        primary_rays: u32,
+    ) -> fmt::Result {
+        self.bvh().write_statistics(out, statistics, primary_rays)
+    }
+}
+
+fn work_groups_count(count: usize) -> u32 {
+    let work_groups_count = count / WORKGROUP_SIZE;
+    let work_groups_count = if count % WORKGROUP_SIZE == 0 {
+        work_groups_count
+    } else {
+        work_groups_count + 1
+    };
+    work_groups_count as u32
+}
@@ -0,0 +1,83 @@
+extern crate vulkano;
+
+use std::sync::Arc;
+
+mod lbvh_morton {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/lbvh_morton.comp"]
+    struct Dummy;
+}
+
+/// Calculates Morton codes of the object centroids in the centroid bounds,
+/// values are the indices of the objects to sort with the codes
+pub struct MortonCoder {
+    pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<lbvh_morton::Layout>,
+            >,
+        >,
+    >,
+    work_groups_count: u32,
+}
+
+impl MortonCoder {
+    pub fn new(queue: Arc<vulkano::device::Queue>, object_count: usize) -> MortonCoder {
+        let device = queue.device();
+
+        let pipeline = Arc::new({
+            let shader =
+                lbvh_morton::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            pipeline.clone(),
+            0,
+        );
+
+        MortonCoder {
+            pipeline,
+            ds_pool,
+            work_groups_count: super::work_groups_count(object_count),
+        }
+    }
+
+    pub fn calculate_codes(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        object_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        centroid_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        keys: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        values: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) -> vulkano::command_buffer::AutoCommandBufferBuilder {
+        let descriptor_set = self.ds_pool
+            .next()
+            .add_buffer(object_bounds)
+            .unwrap()
+            .add_buffer(centroid_bounds)
+            .unwrap()
+            .add_buffer(keys)
+            .unwrap()
+            .add_buffer(values)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        builder
+            .dispatch(
+                [self.work_groups_count, 1, 1],
+                self.pipeline.clone(),
+                descriptor_set,
+                (),
+            )
+            .unwrap()
+    }
+}
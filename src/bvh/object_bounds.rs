@@ -0,0 +1,113 @@
+extern crate vulkano;
+
+use std::sync::Arc;
+
+mod lbvh_bounds {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/lbvh_bounds.comp"]
+    struct Dummy;
+}
+
+/// Finds bounds of every triangle and primitive and bounds of their centroids
+pub struct ObjectBoundsFinder {
+    pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    input_ds: Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+    uniform_buffer_pool: vulkano::buffer::CpuBufferPool<lbvh_bounds::ty::Params>,
+    output_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<lbvh_bounds::Layout>,
+            >,
+        >,
+    >,
+    primitive_count: usize,
+    work_groups_count: u32,
+}
+
+impl ObjectBoundsFinder {
+    pub fn new(
+        queue: Arc<vulkano::device::Queue>,
+        positions: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        indices: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        primitive_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        triangle_count: usize,
+        primitive_count: usize,
+    ) -> ObjectBoundsFinder {
+        let device = queue.device();
+
+        let pipeline = Arc::new({
+            let shader =
+                lbvh_bounds::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let input_ds = Arc::new(
+            vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+                pipeline.clone(),
+                0,
+            ).add_buffer(positions)
+                .unwrap()
+                .add_buffer(indices)
+                .unwrap()
+                .add_buffer(primitive_bounds)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let uniform_buffer_pool = vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone());
+        let output_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            pipeline.clone(),
+            1,
+        );
+
+        ObjectBoundsFinder {
+            pipeline,
+            input_ds,
+            uniform_buffer_pool,
+            output_ds_pool,
+            primitive_count,
+            work_groups_count: super::work_groups_count(triangle_count + primitive_count),
+        }
+    }
+
+    /// `centroid_bounds` must be initialized with the empty bounds
+    pub fn find_bounds(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        object_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        centroid_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) -> vulkano::command_buffer::AutoCommandBufferBuilder {
+        let parameters = self.uniform_buffer_pool
+            .next(lbvh_bounds::ty::Params {
+                primitive_count: self.primitive_count as u32,
+            })
+            .expect("failed to create parameters buffer");
+
+        let output_ds = self.output_ds_pool
+            .next()
+            .add_buffer(parameters)
+            .unwrap()
+            .add_buffer(object_bounds)
+            .unwrap()
+            .add_buffer(centroid_bounds)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        builder
+            .dispatch(
+                [self.work_groups_count, 1, 1],
+                self.pipeline.clone(),
+                (self.input_ds.clone(), output_ds),
+                (),
+            )
+            .unwrap()
+    }
+}
@@ -0,0 +1,236 @@
+extern crate vulkano;
+
+use std::iter;
+use std::sync::Arc;
+
+/// Bits sorted by every pass, must match `RADIX` in the shaders
+const RADIX_BITS: u32 = 4;
+/// Even number of passes, so the sorted keys end up in the input buffers
+const PASS_COUNT: u32 = 32 / RADIX_BITS;
+
+mod radix_histogram {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/radix_histogram.comp"]
+    struct Dummy;
+}
+
+mod radix_scan {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/radix_scan.comp"]
+    struct Dummy;
+}
+
+mod radix_scatter {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/radix_scatter.comp"]
+    struct Dummy;
+}
+
+/// Stable least significant digit radix sort of the keys with the values on the GPU.
+/// Every pass counts the digits in every work group, scans the counts
+/// and scatters the keys to their positions.
+pub struct RadixSorter {
+    histogram_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    histogram_params_pool: vulkano::buffer::CpuBufferPool<radix_histogram::ty::Params>,
+    histogram_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<radix_histogram::Layout>,
+            >,
+        >,
+    >,
+    scan_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    scan_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<radix_scan::Layout>,
+            >,
+        >,
+    >,
+    scatter_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    scatter_params_pool: vulkano::buffer::CpuBufferPool<radix_scatter::ty::Params>,
+    scatter_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<radix_scatter::Layout>,
+            >,
+        >,
+    >,
+    count: usize,
+    work_groups_count: u32,
+}
+
+impl RadixSorter {
+    pub fn new(queue: Arc<vulkano::device::Queue>, count: usize) -> RadixSorter {
+        let device = queue.device();
+
+        let histogram_pipeline = Arc::new({
+            let shader = radix_histogram::Shader::load(device.clone())
+                .expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+        let scan_pipeline = Arc::new({
+            let shader =
+                radix_scan::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+        let scatter_pipeline = Arc::new({
+            let shader = radix_scatter::Shader::load(device.clone())
+                .expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let histogram_ds_pool =
+            vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+                histogram_pipeline.clone(),
+                0,
+            );
+        let scan_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            scan_pipeline.clone(),
+            0,
+        );
+        let scatter_ds_pool =
+            vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+                scatter_pipeline.clone(),
+                0,
+            );
+
+        RadixSorter {
+            histogram_pipeline,
+            histogram_params_pool: vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone()),
+            histogram_ds_pool,
+            scan_pipeline,
+            scan_ds_pool,
+            scatter_pipeline,
+            scatter_params_pool: vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone()),
+            scatter_ds_pool,
+            count,
+            work_groups_count: super::work_groups_count(count),
+        }
+    }
+
+    pub fn sort(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        queue: Arc<vulkano::device::Queue>,
+        keys: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        values: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) -> vulkano::command_buffer::AutoCommandBufferBuilder {
+        let device = queue.device();
+        let count = self.count;
+        let temporary_buffer = || -> Arc<vulkano::buffer::BufferAccess + Send + Sync> {
+            vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+                device.clone(),
+                count,
+                vulkano::buffer::BufferUsage::all(),
+                iter::once(queue.family()),
+            ).expect("failed to create sort buffer")
+        };
+        let temporary_keys = temporary_buffer();
+        let temporary_values = temporary_buffer();
+        let histograms = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            (1 << RADIX_BITS) * self.work_groups_count as usize,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create histograms buffer");
+
+        let mut builder = builder;
+        for pass in 0..PASS_COUNT {
+            let (keys_in, values_in, keys_out, values_out) = if pass % 2 == 0 {
+                (
+                    keys.clone(),
+                    values.clone(),
+                    temporary_keys.clone(),
+                    temporary_values.clone(),
+                )
+            } else {
+                (
+                    temporary_keys.clone(),
+                    temporary_values.clone(),
+                    keys.clone(),
+                    values.clone(),
+                )
+            };
+            let shift = pass * RADIX_BITS;
+
+            let histogram_params = self.histogram_params_pool
+                .next(radix_histogram::ty::Params { shift })
+                .expect("failed to create parameters buffer");
+            let histogram_ds = self.histogram_ds_pool
+                .next()
+                .add_buffer(histogram_params)
+                .unwrap()
+                .add_buffer(keys_in.clone())
+                .unwrap()
+                .add_buffer(histograms.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+
+            let scan_ds = self.scan_ds_pool
+                .next()
+                .add_buffer(histograms.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+
+            let scatter_params = self.scatter_params_pool
+                .next(radix_scatter::ty::Params { shift })
+                .expect("failed to create parameters buffer");
+            let scatter_ds = self.scatter_ds_pool
+                .next()
+                .add_buffer(scatter_params)
+                .unwrap()
+                .add_buffer(histograms.clone())
+                .unwrap()
+                .add_buffer(keys_in)
+                .unwrap()
+                .add_buffer(values_in)
+                .unwrap()
+                .add_buffer(keys_out)
+                .unwrap()
+                .add_buffer(values_out)
+                .unwrap()
+                .build()
+                .unwrap();
+
+            builder = builder
+                .dispatch(
+                    [self.work_groups_count, 1, 1],
+                    self.histogram_pipeline.clone(),
+                    histogram_ds,
+                    (),
+                )
+                .unwrap()
+                .dispatch([1, 1, 1], self.scan_pipeline.clone(), scan_ds, ())
+                .unwrap()
+                .dispatch(
+                    [self.work_groups_count, 1, 1],
+                    self.scatter_pipeline.clone(),
+                    scatter_ds,
+                    (),
+                )
+                .unwrap();
+        }
+        builder
+    }
+}
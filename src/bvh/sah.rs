@@ -0,0 +1,579 @@
+extern crate vulkano;
+
+use super::{Bvh, STACK_SIZE};
+
+use vulkano::sync::GpuFuture;
+
+use std::f32;
+use std::sync::Arc;
+use std::thread;
+
+/// Number of bins every axis is divided into to find the split
+const BIN_COUNT: usize = 16;
+/// Leaves with more objects are split even if the heuristic says they are cheaper
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of the node traversal relative to the cost of the object intersection
+const TRAVERSAL_COST: f32 = 1.0;
+/// Subtrees with less objects are built in the thread of their parent
+const PARALLEL_THRESHOLD: usize = 4096;
+/// Children of the nodes up to this depth are built in parallel, so there are
+/// up to `2 ^ MAX_PARALLEL_DEPTH` threads
+const MAX_PARALLEL_DEPTH: usize = 4;
+/// Nodes this deep are leaves however many objects they have, so the traversal stack
+/// holds the deepest path
+const MAX_DEPTH: usize = STACK_SIZE - 1;
+// must match the constant in accel/bvh.glsl.tera
+const BVH_LEAF: u32 = 0x8000_0000;
+
+/// Minimum and maximum corners
+pub type Bounds = ([f32; 3], [f32; 3]);
+
+const EMPTY_BOUNDS: Bounds = ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+
+/// Node in the layout of `BvhNode` in accel/bvh.glsl.tera
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Node {
+    pub min: [f32; 3],
+    /// The left child or the first reference of the leaf
+    pub left: u32,
+    pub max: [f32; 3],
+    /// The right child or `BVH_LEAF | count` of the leaf
+    pub right: u32,
+}
+
+/// BVH built on the CPU with the binned surface area heuristic. It takes much longer
+/// to build than the LBVH, but rays traverse less nodes, so it is built once.
+pub struct SahBvh {
+    /// The root is the first node
+    pub nodes: Vec<Node>,
+    /// Objects referenced by the leaves
+    pub references: Vec<u32>,
+}
+
+enum BuildNode {
+    Leaf {
+        bounds: Bounds,
+        objects: Vec<u32>,
+    },
+    Interior {
+        bounds: Bounds,
+        left: Box<BuildNode>,
+        right: Box<BuildNode>,
+    },
+}
+
+struct Split {
+    axis: usize,
+    /// Objects in this and lower bins go to the left child
+    bin: usize,
+    cost: f32,
+}
+
+impl SahBvh {
+    /// Objects are referenced by their position in `object_bounds`
+    pub fn new(object_bounds: &[Bounds]) -> SahBvh {
+        let objects = (0..object_bounds.len() as u32).collect();
+        let root = build_node(Arc::new(object_bounds.to_vec()), objects, 0);
+        let mut bvh = SahBvh {
+            nodes: Vec::new(),
+            references: Vec::with_capacity(object_bounds.len()),
+        };
+        bvh.flatten(root);
+        bvh
+    }
+
+    /// Uploads the nodes and references, the returned BVH can be used right away
+    pub fn upload(&self, queue: Arc<vulkano::device::Queue>) -> Bvh {
+        let (nodes_buffer, nodes_future) = vulkano::buffer::ImmutableBuffer::from_iter(
+            self.nodes.iter().cloned(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        ).expect("failed to create nodes buffer");
+        // buffer can't be empty, the root of the empty hierarchy has no references
+        let references = if self.references.is_empty() {
+            vec![0]
+        } else {
+            self.references.clone()
+        };
+        let (references_buffer, references_future) = vulkano::buffer::ImmutableBuffer::from_iter(
+            references.into_iter(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        ).expect("failed to create references buffer");
+        nodes_future
+            .join(references_future)
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        Bvh {
+            node_count: self.nodes.len(),
+            nodes_buffer,
+            references_buffer,
+        }
+    }
+
+    /// Writes the subtree in the depth-first order, returns the index of its root
+    fn flatten(&mut self, node: BuildNode) -> u32 {
+        let node_idx = self.nodes.len() as u32;
+        match node {
+            BuildNode::Leaf { bounds, objects } => {
+                self.nodes.push(Node {
+                    min: bounds.0,
+                    left: self.references.len() as u32,
+                    max: bounds.1,
+                    right: BVH_LEAF | objects.len() as u32,
+                });
+                self.references.extend(objects);
+            }
+            BuildNode::Interior {
+                bounds,
+                left,
+                right,
+            } => {
+                self.nodes.push(Node {
+                    min: bounds.0,
+                    left: 0,
+                    max: bounds.1,
+                    right: 0,
+                });
+                let left = self.flatten(*left);
+                let right = self.flatten(*right);
+                let node = &mut self.nodes[node_idx as usize];
+                node.left = left;
+                node.right = right;
+            }
+        }
+        node_idx
+    }
+}
+
+fn build_node(object_bounds: Arc<Vec<Bounds>>, objects: Vec<u32>, depth: usize) -> BuildNode {
+    let mut bounds = EMPTY_BOUNDS;
+    let mut centroid_bounds = EMPTY_BOUNDS;
+    for &object in &objects {
+        let object = &object_bounds[object as usize];
+        let center = centroid(object);
+        bounds = union(&bounds, object);
+        centroid_bounds = union(&centroid_bounds, &(center, center));
+    }
+
+    let split = if objects.len() > 1 && depth < MAX_DEPTH {
+        find_split(&object_bounds, &objects, &bounds, &centroid_bounds)
+    } else {
+        None
+    };
+    let split = match split {
+        Some(split) => split,
+        None => return BuildNode::Leaf { bounds, objects },
+    };
+    if split.cost >= objects.len() as f32 && objects.len() <= MAX_LEAF_SIZE {
+        return BuildNode::Leaf { bounds, objects };
+    }
+
+    let parallel = objects.len() >= PARALLEL_THRESHOLD && depth < MAX_PARALLEL_DEPTH;
+    let (left_objects, right_objects): (Vec<u32>, Vec<u32>) =
+        objects.into_iter().partition(|&object| {
+            let center = centroid(&object_bounds[object as usize]);
+            bin_index(&center, split.axis, &centroid_bounds) <= split.bin
+        });
+    let (left, right) = if parallel {
+        let left_bounds = object_bounds.clone();
+        let left = thread::spawn(move || build_node(left_bounds, left_objects, depth + 1));
+        let right = build_node(object_bounds, right_objects, depth + 1);
+        (left.join().expect("BVH build thread panicked"), right)
+    } else {
+        (
+            build_node(object_bounds.clone(), left_objects, depth + 1),
+            build_node(object_bounds, right_objects, depth + 1),
+        )
+    };
+    BuildNode::Interior {
+        bounds,
+        left: Box::new(left),
+        right: Box::new(right),
+    }
+}
+
+/// The cheapest split between the bins along any axis, `None` if all centroids are the same
+fn find_split(
+    object_bounds: &[Bounds],
+    objects: &[u32],
+    bounds: &Bounds,
+    centroid_bounds: &Bounds,
+) -> Option<Split> {
+    let area = surface_area(bounds).max(f32::MIN_POSITIVE);
+    let mut best: Option<Split> = None;
+    for axis in 0..3 {
+        if centroid_bounds.1[axis] <= centroid_bounds.0[axis] {
+            continue;
+        }
+        let mut bin_counts = [0usize; BIN_COUNT];
+        let mut bin_bounds = [EMPTY_BOUNDS; BIN_COUNT];
+        for &object in objects {
+            let object = &object_bounds[object as usize];
+            let bin = bin_index(&centroid(object), axis, centroid_bounds);
+            bin_counts[bin] += 1;
+            bin_bounds[bin] = union(&bin_bounds[bin], object);
+        }
+
+        // cost of the objects to the right of every split
+        let mut right_costs = [0.0; BIN_COUNT];
+        let mut right_bounds = EMPTY_BOUNDS;
+        let mut right_count = 0;
+        for bin in (1..BIN_COUNT).rev() {
+            right_bounds = union(&right_bounds, &bin_bounds[bin]);
+            right_count += bin_counts[bin];
+            right_costs[bin - 1] = right_count as f32 * surface_area(&right_bounds);
+        }
+
+        let mut left_bounds = EMPTY_BOUNDS;
+        let mut left_count = 0;
+        for bin in 0..BIN_COUNT - 1 {
+            left_bounds = union(&left_bounds, &bin_bounds[bin]);
+            left_count += bin_counts[bin];
+            if left_count == 0 || left_count == objects.len() {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (left_count as f32 * surface_area(&left_bounds) + right_costs[bin]) / area;
+            if best.as_ref().map_or(true, |best| cost < best.cost) {
+                best = Some(Split { axis, bin, cost });
+            }
+        }
+    }
+    best
+}
+
+fn bin_index(center: &[f32; 3], axis: usize, centroid_bounds: &Bounds) -> usize {
+    let extent = centroid_bounds.1[axis] - centroid_bounds.0[axis];
+    let relative = (center[axis] - centroid_bounds.0[axis]) / extent;
+    ((relative * BIN_COUNT as f32) as usize).min(BIN_COUNT - 1)
+}
+
+fn union(a: &Bounds, b: &Bounds) -> Bounds {
+    let mut result = *a;
+    for axis in 0..3 {
+        result.0[axis] = a.0[axis].min(b.0[axis]);
+        result.1[axis] = a.1[axis].max(b.1[axis]);
+    }
+    result
+}
+
+fn centroid(bounds: &Bounds) -> [f32; 3] {
+    [
+        0.5 * (bounds.0[0] + bounds.1[0]),
+        0.5 * (bounds.0[1] + bounds.1[1]),
+        0.5 * (bounds.0[2] + bounds.1[2]),
+    ]
+}
+
+fn surface_area(bounds: &Bounds) -> f32 {
+    let dx = (bounds.1[0] - bounds.0[0]).max(0.0);
+    let dy = (bounds.1[1] - bounds.0[1]).max(0.0);
+    let dz = (bounds.1[2] - bounds.0[2]).max(0.0);
+    2.0 * (dx * dy + dy * dz + dz * dx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32;
+
+    impl Node {
+        fn is_leaf(&self) -> bool {
+            self.right & BVH_LEAF != 0
+        }
+    }
+
+    impl SahBvh {
+        /// The closest object hit by the ray and the time of the hit, `intersect_object`
+        /// returns the time, when the ray hits the object. Traverses the hierarchy
+        /// the same way as the tracer, e.g. to check it against brute-force tests.
+        fn intersect<F>(
+            &self,
+            origin: [f32; 3],
+            direction: [f32; 3],
+            mut intersect_object: F,
+        ) -> Option<(u32, f32)>
+        where
+            F: FnMut(u32) -> Option<f32>,
+        {
+            let inv_dir = [1.0 / direction[0], 1.0 / direction[1], 1.0 / direction[2]];
+            let mut best: Option<(u32, f32)> = None;
+            let mut stack = vec![0];
+            while let Some(node_idx) = stack.pop() {
+                let node = self.nodes[node_idx as usize];
+                let t_enter = match intersect_bounds(&(node.min, node.max), origin, inv_dir) {
+                    Some(t_enter) => t_enter,
+                    None => continue,
+                };
+                if best.map_or(false, |(_, time)| time < t_enter) {
+                    continue;
+                }
+                if !node.is_leaf() {
+                    stack.push(node.left);
+                    stack.push(node.right);
+                    continue;
+                }
+                let first = node.left as usize;
+                let count = (node.right & !BVH_LEAF) as usize;
+                for &object in &self.references[first..first + count] {
+                    if let Some(time) = intersect_object(object) {
+                        if best.map_or(true, |(_, best_time)| time < best_time) {
+                            best = Some((object, time));
+                        }
+                    }
+                }
+            }
+            best
+        }
+    }
+
+    /// Möller–Trumbore ray-triangle intersection, the same as in tracer.comp.tera
+    fn intersect_triangle(
+        origin: [f32; 3],
+        direction: [f32; 3],
+        triangle: [[f32; 3]; 3],
+    ) -> Option<f32> {
+        const EPSILON: f32 = 1e-8;
+        let v0v1 = sub(&triangle[1], &triangle[0]);
+        let v0v2 = sub(&triangle[2], &triangle[0]);
+        let pvec = cross(&direction, &v0v2);
+        let det = dot(&v0v1, &pvec);
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = sub(&origin, &triangle[0]);
+        let u = dot(&tvec, &pvec) * inv_det;
+        if u < 0.0 || u > 1.0 {
+            return None;
+        }
+        let qvec = cross(&tvec, &v0v1);
+        let v = dot(&direction, &qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let time = dot(&v0v2, &qvec) * inv_det;
+        if time >= 0.0 {
+            Some(time)
+        } else {
+            None
+        }
+    }
+
+    /// Time, when the ray enters the bounds, if it hits them
+    fn intersect_bounds(bounds: &Bounds, origin: [f32; 3], inv_dir: [f32; 3]) -> Option<f32> {
+        let mut t_enter = f32::NEG_INFINITY;
+        let mut t_exit = f32::INFINITY;
+        for axis in 0..3 {
+            let t0 = (bounds.0[axis] - origin[axis]) * inv_dir[axis];
+            let t1 = (bounds.1[axis] - origin[axis]) * inv_dir[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter <= t_exit && t_exit >= 0.0 {
+            Some(t_enter.max(0.0))
+        } else {
+            None
+        }
+    }
+
+    fn sub(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn dot(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    fn cross(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+
+    /// Xorshift, so the soups and rays are the same in every run
+    struct Random(u32);
+
+    impl Random {
+        /// In `[min, max)`
+        fn next(&mut self, min: f32, max: f32) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            min + (max - min) * (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+
+        fn point(&mut self, min: f32, max: f32) -> [f32; 3] {
+            [
+                self.next(min, max),
+                self.next(min, max),
+                self.next(min, max),
+            ]
+        }
+    }
+
+    /// Small triangles scattered over `[-10, 10)` on every axis
+    fn random_triangles(random: &mut Random, count: usize) -> Vec<[[f32; 3]; 3]> {
+        (0..count)
+            .map(|_| {
+                let a = random.point(-10.0, 10.0);
+                let b = random.point(-1.0, 1.0);
+                let c = random.point(-1.0, 1.0);
+                [
+                    a,
+                    [a[0] + b[0], a[1] + b[1], a[2] + b[2]],
+                    [a[0] + c[0], a[1] + c[1], a[2] + c[2]],
+                ]
+            })
+            .collect()
+    }
+
+    fn triangle_bounds(triangle: &[[f32; 3]; 3]) -> Bounds {
+        triangle.iter().fold(EMPTY_BOUNDS, |bounds, &point| {
+            union(&bounds, &(point, point))
+        })
+    }
+
+    /// The closest triangle hit by the ray, tested one by one
+    fn brute_force(
+        triangles: &[[[f32; 3]; 3]],
+        origin: [f32; 3],
+        direction: [f32; 3],
+    ) -> Option<(u32, f32)> {
+        let mut best: Option<(u32, f32)> = None;
+        for (idx, triangle) in triangles.iter().enumerate() {
+            if let Some(time) = intersect_triangle(origin, direction, *triangle) {
+                if best.map_or(true, |(_, best_time)| time < best_time) {
+                    best = Some((idx as u32, time));
+                }
+            }
+        }
+        best
+    }
+
+    /// Fires rays from around the soup to its random points, every hit must be the same
+    /// as the brute-force one. Returns the number of rays, which hit anything.
+    fn check_rays(triangles: &[[[f32; 3]; 3]], random: &mut Random) -> usize {
+        let bounds: Vec<_> = triangles.iter().map(triangle_bounds).collect();
+        let bvh = SahBvh::new(&bounds);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = random.point(-20.0, 20.0);
+            let target = random.point(-10.0, 10.0);
+            let direction = sub(&target, &origin);
+            let expected = brute_force(triangles, origin, direction);
+            let actual = bvh.intersect(origin, direction, |object| {
+                intersect_triangle(origin, direction, triangles[object as usize])
+            });
+            assert_eq!(actual, expected, "ray from {:?} to {:?}", origin, target);
+            if expected.is_some() {
+                hits += 1;
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn references_every_object_once() {
+        let mut random = Random(7);
+        let bounds: Vec<_> = (0..1000)
+            .map(|_| {
+                let center = random.point(-10.0, 10.0);
+                (center, [center[0] + 0.5, center[1] + 0.5, center[2] + 0.5])
+            })
+            .collect();
+        let bvh = SahBvh::new(&bounds);
+        let mut references = bvh.references.clone();
+        references.sort();
+        assert_eq!(references, (0..1000).collect::<Vec<u32>>());
+        assert!(bvh.nodes.len() > 1);
+    }
+
+    #[test]
+    fn empty_soup() {
+        let bvh = SahBvh::new(&[]);
+        assert_eq!(bvh.nodes.len(), 1);
+        assert!(bvh.references.is_empty());
+        assert_eq!(
+            bvh.intersect([0.0; 3], [1.0, 1.0, 1.0], |_| Some(1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn random_soup() {
+        let mut random = Random(1);
+        let triangles = random_triangles(&mut random, 500);
+        assert!(triangles.len() > MAX_LEAF_SIZE);
+        assert!(check_rays(&triangles, &mut random) > 0);
+    }
+
+    #[test]
+    fn parallel_soup() {
+        // large enough for the subtrees of the root to be built in their own threads
+        let mut random = Random(4);
+        let triangles = random_triangles(&mut random, 2 * PARALLEL_THRESHOLD);
+        let bounds: Vec<_> = triangles.iter().map(triangle_bounds).collect();
+        let mut references = SahBvh::new(&bounds).references;
+        references.sort();
+        assert_eq!(
+            references,
+            (0..triangles.len() as u32).collect::<Vec<u32>>()
+        );
+        assert!(check_rays(&triangles, &mut random) > 0);
+    }
+
+    #[test]
+    fn coplanar_soup() {
+        // triangles of a single plane with gaps between them, so no ray hits two at once
+        let mut triangles = Vec::new();
+        for x in 0..16 {
+            for y in 0..16 {
+                let (x, y) = (x as f32 - 8.0, y as f32 - 8.0);
+                triangles.push([[x, y, 0.0], [x + 0.9, y, 0.0], [x, y + 0.9, 0.0]]);
+            }
+        }
+        let mut random = Random(2);
+        assert!(check_rays(&triangles, &mut random) > 0);
+    }
+
+    #[test]
+    fn degenerate_soup() {
+        let mut random = Random(3);
+        let mut triangles = Vec::new();
+        for _ in 0..100 {
+            let a = random.point(-10.0, 10.0);
+            let b = random.point(-10.0, 10.0);
+            // zero area: a point, repeated vertices and collinear vertices
+            triangles.push([a, a, a]);
+            triangles.push([a, a, b]);
+            triangles.push([a, b, a]);
+            triangles.push([a, [a[0] + 1.0, a[1], a[2]], [a[0] + 2.0, a[1], a[2]]]);
+        }
+        // triangles with the same centroid, which can't be split by the heuristic
+        for i in 0..3 * MAX_LEAF_SIZE {
+            let angle = i as f32;
+            let (sin, cos) = (angle.sin(), angle.cos());
+            triangles.push([
+                [2.0 * cos, 2.0 * sin, -1.0],
+                [-2.0 * cos, -2.0 * sin, -1.0],
+                [0.0, 0.0, 2.0],
+            ]);
+        }
+        assert!(check_rays(&triangles, &mut random) > 0);
+    }
+}
@@ -23,16 +23,17 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn with_position(position: cgmath::Vector3<f32>, fov: [f32; 2]) -> Camera {
-        const DEFAULT_YAW: f32 = 0.0;
-        const DEFAULT_PITCH: f32 = 0.0;
-        Camera {
+    pub fn new(position: cgmath::Vector3<f32>, yaw: f32, pitch: f32, fov: [f32; 2]) -> Camera {
+        let mut camera = Camera {
             position: position,
-            view_dir: view_direction(DEFAULT_YAW, DEFAULT_PITCH),
+            view_dir: view_direction(0.0, 0.0),
             fov: fov,
-            yaw: DEFAULT_YAW,
-            pitch: DEFAULT_PITCH,
-        }
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        camera.add_yaw(yaw);
+        camera.add_pitch(pitch);
+        camera
     }
     pub fn position(&self) -> [f32; 3] {
         self.position.into()
@@ -1,10 +1,8 @@
 extern crate vulkano;
 
-use vulkano::sync::GpuFuture;
+use gl_types::Vec3;
 
-use gl_types::{FromArr3, Vec3, vec3_max, vec3_min};
-
-use std::f32;
+use std::iter;
 use std::sync::Arc;
 
 const WORKGROUP_SIZE: usize = 256;
@@ -17,27 +15,42 @@ mod bbox {
     struct Dummy;
 }
 
+mod bbox_reduce {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/bbox_reduce.comp"]
+    struct Dummy;
+}
+
 pub struct BBox {
     pub min: Vec3,
     pub max: Vec3,
 }
 
-pub struct BBoxFinder {
+/// Reduction pass with the number of its work groups
+struct ReducePass {
     pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
-    output_buffer: Arc<vulkano::buffer::CpuAccessibleBuffer<[Vec3]>>,
     descriptor_set: Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
     work_groups_count: usize,
 }
 
+/// Finds the bbox of the positions on the GPU. Every pass reduces the minimums
+/// and maximums of the previous one until a single work group is left.
+pub struct BBoxFinder {
+    passes: Vec<ReducePass>,
+    output_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+}
+
 impl BBoxFinder {
     pub fn new(
         queue: Arc<vulkano::device::Queue>,
         positions: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
-        triangle_count: usize,
+        vertex_count: usize,
     ) -> BBoxFinder {
         let device = queue.device();
 
-        let pipeline = Arc::new({
+        let bbox_pipeline = Arc::new({
             let shader =
                 bbox::Shader::load(device.clone()).expect("failed to create shader module");
             vulkano::pipeline::ComputePipeline::new(
@@ -46,84 +59,104 @@ impl BBoxFinder {
                 &(),
             ).expect("failed to create compute pipeline")
         });
+        let reduce_pipeline = Arc::new({
+            let shader =
+                bbox_reduce::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
 
-        let work_groups_count = triangle_count / (2 * WORKGROUP_SIZE);
-        let work_groups_count = if triangle_count % (2 * WORKGROUP_SIZE) == 0 {
-            work_groups_count
-        } else {
-            work_groups_count + 1
-        };
-
-        let output_buffer = {
-            let data_iter = (0..2 * work_groups_count).map(|_| Vec3::from_arr3([-1.0; 3]));
-            vulkano::buffer::CpuAccessibleBuffer::from_iter(
+        // minimums of the work groups followed by their maximums
+        let new_output_buffer = |work_groups_count| {
+            vulkano::buffer::DeviceLocalBuffer::<[Vec3]>::array(
                 device.clone(),
+                2 * work_groups_count,
                 vulkano::buffer::BufferUsage::all(),
-                data_iter,
-            ).expect("failed to create buffer")
+                iter::once(queue.family()),
+            ).expect("failed to create bbox buffer")
         };
 
-        let descriptor_set = Arc::new(
-            vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
-                pipeline.clone(),
-                0,
-            ).add_buffer(positions)
-                .unwrap()
-                .add_buffer(output_buffer.clone())
-                .unwrap()
-                .build()
-                .unwrap(),
-        );
+        // every invocation reduces two values
+        let mut work_groups_count = groups_count(vertex_count);
+        let mut output_buffer = new_output_buffer(work_groups_count);
+        let mut passes = vec![
+            ReducePass {
+                pipeline: bbox_pipeline.clone(),
+                descriptor_set: Arc::new(
+                    vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+                        bbox_pipeline.clone(),
+                        0,
+                    ).add_buffer(positions)
+                        .unwrap()
+                        .add_buffer(output_buffer.clone())
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                ),
+                work_groups_count,
+            },
+        ];
+        while work_groups_count > 1 {
+            let input_buffer = output_buffer;
+            work_groups_count = groups_count(work_groups_count);
+            output_buffer = new_output_buffer(work_groups_count);
+            passes.push(ReducePass {
+                pipeline: reduce_pipeline.clone(),
+                descriptor_set: Arc::new(
+                    vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+                        reduce_pipeline.clone(),
+                        0,
+                    ).add_buffer(input_buffer)
+                        .unwrap()
+                        .add_buffer(output_buffer.clone())
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                ),
+                work_groups_count,
+            });
+        }
 
         BBoxFinder {
-            pipeline,
+            passes,
             output_buffer,
-            descriptor_set,
-            work_groups_count,
         }
     }
 
+    /// Records the passes, the returned buffer contains the minimum and the maximum
+    /// after the command buffer is executed
     pub fn calculate_bbox(
         &self,
-        queue: Arc<vulkano::device::Queue>,
-        future: Box<vulkano::sync::GpuFuture>,
-    ) -> BBox {
-        let device = queue.device();
-
-        let command_buffer =
-            vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
-                device.clone(),
-                queue.family(),
-            ).unwrap()
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+    ) -> (
+        vulkano::command_buffer::AutoCommandBufferBuilder,
+        Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) {
+        let mut builder = builder;
+        for pass in &self.passes {
+            builder = builder
                 .dispatch(
-                    [self.work_groups_count as u32, 1, 1],
-                    self.pipeline.clone(),
-                    self.descriptor_set.clone(),
+                    [pass.work_groups_count as u32, 1, 1],
+                    pass.pipeline.clone(),
+                    pass.descriptor_set.clone(),
                     (),
                 )
-                .unwrap()
-                .build()
                 .unwrap();
+        }
+        (builder, self.output_buffer.clone())
+    }
+}
 
-        let future = future
-            .then_execute(queue.clone(), command_buffer)
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
-        future.wait(None).unwrap();
-
-        let output = self.output_buffer
-            .read()
-            .expect("failed to lock bbox output buffer");
-        let (minimum_buffer, maximum_buffer) = output.split_at(self.work_groups_count);
-
-        let min = minimum_buffer
-            .into_iter()
-            .fold(Vec3::from_arr3([f32::MAX; 3]), |min, v| vec3_min(&min, v));
-        let max = maximum_buffer
-            .into_iter()
-            .fold(Vec3::from_arr3([f32::MIN; 3]), |max, v| vec3_max(&max, v));
-
-        BBox { min, max }
+/// Work groups of a pass over `count` values, at least one so the empty scene
+/// still gets the bbox
+fn groups_count(count: usize) -> usize {
+    let work_groups_count = count / (2 * WORKGROUP_SIZE);
+    if count % (2 * WORKGROUP_SIZE) == 0 {
+        work_groups_count.max(1)
+    } else {
+        work_groups_count + 1
     }
 }
@@ -0,0 +1,123 @@
+extern crate vulkano;
+
+use std::f32;
+use std::sync::Arc;
+
+mod grid_params {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/grid_params.comp"]
+    struct Dummy;
+}
+
+pub use self::grid_params::ty::GridParams;
+
+/// Chooses the grid resolution from the bbox on the GPU, so the next passes
+/// don't wait for the bbox to be read back
+pub struct GridParamsCalculator {
+    pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    /// Parameters of the scene, which don't change between the builds
+    params: grid_params::ty::Params,
+    uniform_buffer_pool: vulkano::buffer::CpuBufferPool<grid_params::ty::Params>,
+    ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<grid_params::Layout>,
+            >,
+        >,
+    >,
+}
+
+impl GridParamsCalculator {
+    /// The grid is extended by `primitives_bbox` and has `density` references per cell
+    /// on average, but no more than `max_cell_count` cells
+    pub fn new(
+        queue: Arc<vulkano::device::Queue>,
+        primitives_bbox: Option<([f32; 3], [f32; 3])>,
+        object_count: usize,
+        density: f32,
+        max_cell_count: usize,
+    ) -> GridParamsCalculator {
+        let device = queue.device();
+
+        let pipeline = Arc::new({
+            let shader =
+                grid_params::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let (primitives_min, primitives_max) =
+            primitives_bbox.unwrap_or(([f32::MAX; 3], [f32::MIN; 3]));
+        let params = grid_params::ty::Params {
+            primitives_min,
+            object_count: object_count as u32,
+            primitives_max,
+            density,
+            max_cell_count: max_cell_count as u32,
+        };
+
+        let uniform_buffer_pool = vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone());
+        let ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            pipeline.clone(),
+            0,
+        );
+
+        GridParamsCalculator {
+            pipeline,
+            params,
+            uniform_buffer_pool,
+            ds_pool,
+        }
+    }
+
+    /// Records the pass, which chooses the grid parameters by the bbox of the triangles
+    /// in `bbox_buffer`. The returned parameters can be read after the execution.
+    pub fn calculate(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        queue: Arc<vulkano::device::Queue>,
+        bbox_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    ) -> (
+        vulkano::command_buffer::AutoCommandBufferBuilder,
+        Arc<vulkano::buffer::CpuAccessibleBuffer<GridParams>>,
+    ) {
+        let params_buffer = self.uniform_buffer_pool
+            .next(self.params)
+            .expect("failed to create params buffer");
+
+        let grid_params_buffer = vulkano::buffer::CpuAccessibleBuffer::from_data(
+            queue.device().clone(),
+            vulkano::buffer::BufferUsage::all(),
+            GridParams {
+                min_cell: [0.0; 3],
+                grid_object_count: 0,
+                max_cell: [0.0; 3],
+                cell_count: 0,
+                cell_size: [0.0; 3],
+                resolution: [0; 3],
+                _dummy0: [0; 4],
+            },
+        ).expect("failed to create grid params buffer");
+
+        let descriptor_set = self.ds_pool
+            .next()
+            .add_buffer(params_buffer)
+            .unwrap()
+            .add_buffer(bbox_buffer)
+            .unwrap()
+            .add_buffer(grid_params_buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let builder = builder
+            .dispatch([1, 1, 1], self.pipeline.clone(), descriptor_set, ())
+            .unwrap();
+        (builder, grid_params_buffer)
+    }
+}
@@ -0,0 +1,189 @@
+extern crate vulkano;
+
+use super::TopLevel;
+use super::leaf_resolution::TopCells;
+use super::pair_counter::CountedPairs;
+use super::prefix_scan::PrefixScanner;
+
+use std::iter;
+use std::sync::Arc;
+
+const WORKGROUP_SIZE: usize = 256;
+
+mod count_leaf_pairs {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/count_leaf_pairs.comp"]
+    struct Dummy;
+}
+
+/// Counts references in the leaf cells, every top level pair is counted separately
+pub struct LeafPairCounter {
+    pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    input_ds: Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+    uniform_buffer_pool: vulkano::buffer::CpuBufferPool<count_leaf_pairs::ty::Params>,
+    output_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<count_leaf_pairs::Layout>,
+            >,
+        >,
+    >,
+    prefix_scanner: PrefixScanner,
+    primitive_count: usize,
+}
+
+impl LeafPairCounter {
+    pub fn new(
+        queue: Arc<vulkano::device::Queue>,
+        positions: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        indices: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        primitive_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        primitive_count: usize,
+    ) -> LeafPairCounter {
+        let device = queue.device();
+
+        let pipeline = Arc::new({
+            let shader = count_leaf_pairs::Shader::load(device.clone())
+                .expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let input_ds = Arc::new(
+            vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+                pipeline.clone(),
+                0,
+            ).add_buffer(positions)
+                .unwrap()
+                .add_buffer(indices)
+                .unwrap()
+                .add_buffer(primitive_bounds)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let uniform_buffer_pool = vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone());
+        let output_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            pipeline.clone(),
+            1,
+        );
+        let prefix_scanner = PrefixScanner::new(queue.clone());
+
+        LeafPairCounter {
+            pipeline,
+            input_ds,
+            uniform_buffer_pool,
+            output_ds_pool,
+            prefix_scanner,
+            primitive_count,
+        }
+    }
+
+    /// Records counting of the references in the leaf cells and their scan. The leaf cells
+    /// buffer has room for the bound of the leaf cell count, which is known beforehand.
+    pub fn count_pairs(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        queue: Arc<vulkano::device::Queue>,
+        top_level: &TopLevel,
+        top_cells: &TopCells,
+    ) -> (vulkano::command_buffer::AutoCommandBufferBuilder, CountedPairs) {
+        let device = queue.device();
+        let pair_count = top_level.pair_count;
+        let max_leaf_cell_count = top_cells.max_leaf_cell_count;
+
+        let parameters = self.uniform_buffer_pool
+            .next(count_leaf_pairs::ty::Params {
+                min_cell: top_level.min_cell,
+                cell_size: top_level.cell_size,
+                resolution: top_level.resolution,
+                primitive_count: self.primitive_count as u32,
+                _dummy0: [0; 4],
+                _dummy1: [0; 4],
+            })
+            .expect("failed to create parameters buffer");
+
+        // create one more cell so the last one contains total references count
+        let leaf_cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            max_leaf_cell_count + 1,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create leaf cells buffer");
+
+        let min_cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[[u32; 4]]>::array(
+            device.clone(),
+            pair_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("can't create references buffer");
+
+        let max_cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[[u32; 4]]>::array(
+            device.clone(),
+            pair_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("can't create references buffer");
+
+        let output_ds = self.output_ds_pool
+            .next()
+            .add_buffer(parameters)
+            .unwrap()
+            .add_buffer(top_level.cells_buffer.clone())
+            .unwrap()
+            .add_buffer(top_level.references_buffer.clone())
+            .unwrap()
+            .add_buffer(top_cells.top_cells_buffer.clone())
+            .unwrap()
+            .add_buffer(leaf_cells_buffer.clone())
+            .unwrap()
+            .add_buffer(min_cells_buffer.clone())
+            .unwrap()
+            .add_buffer(max_cells_buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let work_groups_count = pair_count / WORKGROUP_SIZE;
+        let work_groups_count = if pair_count % WORKGROUP_SIZE == 0 {
+            work_groups_count
+        } else {
+            work_groups_count + 1
+        };
+
+        let builder = builder
+            .fill_buffer(leaf_cells_buffer.clone(), 0)
+            .unwrap()
+            .dispatch(
+                [work_groups_count as u32, 1, 1],
+                self.pipeline.clone(),
+                (self.input_ds.clone(), output_ds),
+                (),
+            )
+            .unwrap();
+
+        // cells past the leaf cell count stay empty, so they don't change the total count
+        let (builder, pair_count_buffer) = self.prefix_scanner.scan(
+            builder,
+            queue.clone(),
+            leaf_cells_buffer.clone(),
+            max_leaf_cell_count + 1,
+        );
+
+        (
+            builder,
+            CountedPairs {
+                cells_buffer: leaf_cells_buffer,
+                min_cells_buffer,
+                max_cells_buffer,
+                pair_count_buffer,
+            },
+        )
+    }
+}
@@ -0,0 +1,214 @@
+extern crate vulkano;
+
+use super::TopLevel;
+use super::prefix_scan::PrefixScanner;
+
+use std::iter;
+use std::sync::Arc;
+
+const WORKGROUP_SIZE: usize = 256;
+
+mod leaf_resolution {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/leaf_resolution.comp"]
+    struct Dummy;
+}
+
+mod leaf_offsets {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/leaf_offsets.comp"]
+    struct Dummy;
+}
+
+pub struct LeafResolution {
+    resolution_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    offsets_pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    uniform_buffer_pool: vulkano::buffer::CpuBufferPool<leaf_resolution::ty::Params>,
+    resolution_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<leaf_resolution::Layout>,
+            >,
+        >,
+    >,
+    offsets_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<leaf_offsets::Layout>,
+            >,
+        >,
+    >,
+    prefix_scanner: PrefixScanner,
+}
+
+/// Buffers of the recorded pass, which are valid after the command buffer is executed
+pub struct TopCells {
+    /// Leaf grid resolution of every top level cell and index of its first leaf cell
+    pub top_cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Total count of the leaf cells
+    pub leaf_cell_count_buffer: Arc<vulkano::buffer::CpuAccessibleBuffer<[u32]>>,
+    /// Upper bound of the leaf cell count, which the leaf cells are allocated for
+    pub max_leaf_cell_count: usize,
+}
+
+impl LeafResolution {
+    pub fn new(queue: Arc<vulkano::device::Queue>) -> LeafResolution {
+        let device = queue.device();
+
+        let resolution_pipeline = Arc::new({
+            let shader = leaf_resolution::Shader::load(device.clone())
+                .expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+        let offsets_pipeline = Arc::new({
+            let shader = leaf_offsets::Shader::load(device.clone())
+                .expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let uniform_buffer_pool = vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone());
+        let resolution_ds_pool =
+            vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+                resolution_pipeline.clone(),
+                0,
+            );
+        let offsets_ds_pool =
+            vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+                offsets_pipeline.clone(),
+                0,
+            );
+        let prefix_scanner = PrefixScanner::new(queue.clone());
+
+        LeafResolution {
+            resolution_pipeline,
+            offsets_pipeline,
+            uniform_buffer_pool,
+            resolution_ds_pool,
+            offsets_ds_pool,
+            prefix_scanner,
+        }
+    }
+
+    /// Records the choice of the leaf grid resolution of every top level cell by the number
+    /// of references in it, so that leaf cells have about `density` references on average.
+    /// Leaf cell counts are scanned into the first leaf cells on the GPU.
+    pub fn calculate(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        queue: Arc<vulkano::device::Queue>,
+        top_level: &TopLevel,
+        density: f32,
+        max_resolution: u32,
+    ) -> (vulkano::command_buffer::AutoCommandBufferBuilder, TopCells) {
+        let device = queue.device();
+
+        // leaf grids are coarsened to this many cells per reference, so their total count
+        // doesn't exceed the bound, even though it isn't known until the pass is executed
+        let max_cells_per_reference = density.ceil() as u32;
+        let resolution = top_level.resolution;
+        let cell_count = (resolution[0] * resolution[1] * resolution[2]) as usize;
+        let max_leaf_cell_count =
+            max_cells_per_reference as usize * (top_level.pair_count + cell_count);
+
+        let params_buffer = self.uniform_buffer_pool
+            .next(leaf_resolution::ty::Params {
+                cell_size: top_level.cell_size,
+                density,
+                max_resolution,
+                max_cells_per_reference,
+            })
+            .expect("failed to create params buffer");
+
+        let top_cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[[u32; 4]]>::array(
+            device.clone(),
+            cell_count,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create top cells buffer");
+
+        // create one more cell so the last one contains total leaf cell count
+        let leaf_counts_buffer = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            cell_count + 1,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create leaf counts buffer");
+
+        let resolution_ds = self.resolution_ds_pool
+            .next()
+            .add_buffer(params_buffer)
+            .unwrap()
+            .add_buffer(top_level.cells_buffer.clone())
+            .unwrap()
+            .add_buffer(top_cells_buffer.clone())
+            .unwrap()
+            .add_buffer(leaf_counts_buffer.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let work_groups_count = cell_count / WORKGROUP_SIZE;
+        let work_groups_count = if cell_count % WORKGROUP_SIZE == 0 {
+            work_groups_count
+        } else {
+            work_groups_count + 1
+        };
+
+        let builder = builder
+            .fill_buffer(leaf_counts_buffer.clone(), 0)
+            .unwrap()
+            .dispatch(
+                [work_groups_count as u32, 1, 1],
+                self.resolution_pipeline.clone(),
+                resolution_ds,
+                (),
+            )
+            .unwrap();
+
+        let (builder, leaf_cell_count_buffer) = self.prefix_scanner.scan(
+            builder,
+            queue.clone(),
+            leaf_counts_buffer.clone(),
+            cell_count + 1,
+        );
+
+        let offsets_ds = self.offsets_ds_pool
+            .next()
+            .add_buffer(top_cells_buffer.clone())
+            .unwrap()
+            .add_buffer(leaf_counts_buffer)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let builder = builder
+            .dispatch(
+                [work_groups_count as u32, 1, 1],
+                self.offsets_pipeline.clone(),
+                offsets_ds,
+                (),
+            )
+            .unwrap();
+
+        (
+            builder,
+            TopCells {
+                top_cells_buffer,
+                leaf_cell_count_buffer,
+                max_leaf_cell_count,
+            },
+        )
+    }
+}
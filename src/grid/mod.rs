@@ -1,51 +1,145 @@
 extern crate vulkano;
 
 mod bbox;
+mod grid_params;
+mod leaf_pair_counter;
+mod leaf_pair_writer;
+mod leaf_resolution;
 mod pair_counter;
 mod pair_writer;
+mod prefix_scan;
 use self::bbox::{BBox, BBoxFinder};
+use self::grid_params::GridParamsCalculator;
+use self::leaf_pair_counter::LeafPairCounter;
+use self::leaf_pair_writer::LeafPairWriter;
+use self::leaf_resolution::LeafResolution;
 use self::pair_counter::PairCounter;
 use self::pair_writer::PairWriter;
 
+use vulkano::sync::GpuFuture;
+
+use accel::AccelerationStructure;
+use gl_types::{FromArr3, Vec3};
+use scene::ModelBuffers;
+use tracers;
+
+use std::fmt;
+use std::iter;
+use std::mem;
 use std::sync::Arc;
 
+/// Average number of references per cell of the uniform grid
+const UNIFORM_DENSITY: f32 = 5.0;
+/// Top level of the two-level grid is much coarser than the uniform grid
+const TOP_LEVEL_DENSITY: f32 = 1.0 / 16.0;
+/// Average number of references per leaf cell of the two-level grid
+const LEAF_DENSITY: f32 = 1.2;
+/// Limits the leaf grid resolution of the top level cells with huge amount of references
+const MAX_LEAF_RESOLUTION: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GridKind {
+    /// Single-level uniform grid
+    Uniform,
+    /// Uniform top level grid with a uniform leaf grid of its own resolution in every cell,
+    /// which is better for scenes with very uneven triangle distribution
+    TwoLevel,
+}
+
 pub struct Grid {
+    pub kind: GridKind,
     pub bbox: BBox,
+    /// Resolution and cell size of the top level in the two-level grid
     pub resolution: [u32; 3],
     pub cell_size: [f32; 3],
+    /// Leaf cells and references in the two-level grid
     pub cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
     pub references_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Leaf grid resolution and the first leaf cell of every top level cell,
+    /// single dummy cell in the uniform grid
+    pub top_cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Zero in the uniform grid
+    pub leaf_cell_count: usize,
+}
+
+/// Top level of the two-level grid, which is built the same way as the uniform grid
+struct TopLevel {
+    min_cell: [f32; 3],
+    cell_size: [f32; 3],
+    resolution: [u32; 3],
+    cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    references_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pair_count: usize,
 }
 
 pub struct GridBuilder {
     queue: Arc<vulkano::device::Queue>,
+    kind: GridKind,
     bbox_finder: BBoxFinder,
+    grid_params_calculator: GridParamsCalculator,
     pair_counter: PairCounter,
     pair_writer: PairWriter,
-    triangle_count: usize,
+    leaf_resolution: LeafResolution,
+    leaf_pair_counter: LeafPairCounter,
+    leaf_pair_writer: LeafPairWriter,
+    /// Desired number of cells, which grid_params.comp never exceeds
+    max_cell_count: usize,
 }
 
 impl GridBuilder {
     pub fn new(
         queue: Arc<vulkano::device::Queue>,
-        positions: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
-        indices: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
-        triangle_count: usize,
+        scene_buffers: &ModelBuffers,
+        kind: GridKind,
     ) -> GridBuilder {
-        let bbox_finder = BBoxFinder::new(queue.clone(), positions.clone(), triangle_count);
+        let object_count = scene_buffers.triangle_count + scene_buffers.primitive_count;
+        let vertex_count = scene_buffers.positions.size() / mem::size_of::<Vec3>();
+        let bbox_finder = BBoxFinder::new(
+            queue.clone(),
+            scene_buffers.positions.clone(),
+            vertex_count,
+        );
+        let density = match kind {
+            GridKind::Uniform => UNIFORM_DENSITY,
+            GridKind::TwoLevel => TOP_LEVEL_DENSITY,
+        };
+        // the resolution is chosen on the GPU, the cells buffer is allocated for its upper bound
+        let max_cell_count = ((density * object_count as f32) as usize).max(1);
+        let grid_params_calculator = GridParamsCalculator::new(
+            queue.clone(),
+            scene_buffers.primitives_bbox,
+            object_count,
+            density,
+            max_cell_count,
+        );
         let pair_counter = PairCounter::new(
             queue.clone(),
-            positions.clone(),
-            indices.clone(),
-            triangle_count,
+            scene_buffers.positions.clone(),
+            scene_buffers.indices.clone(),
+            scene_buffers.primitive_bounds.clone(),
+            object_count,
+        );
+        let pair_writer = PairWriter::new(queue.clone(), object_count);
+        let leaf_resolution = LeafResolution::new(queue.clone());
+        let leaf_pair_counter = LeafPairCounter::new(
+            queue.clone(),
+            scene_buffers.positions.clone(),
+            scene_buffers.indices.clone(),
+            scene_buffers.primitive_bounds.clone(),
+            scene_buffers.primitive_count,
         );
-        let pair_writer = PairWriter::new(queue.clone(), triangle_count);
+        let leaf_pair_writer = LeafPairWriter::new(queue.clone());
         GridBuilder {
             queue,
+            kind,
             bbox_finder,
+            grid_params_calculator,
             pair_counter,
             pair_writer,
-            triangle_count,
+            leaf_resolution,
+            leaf_pair_counter,
+            leaf_pair_writer,
+            max_cell_count,
         }
     }
 
@@ -53,48 +147,266 @@ impl GridBuilder {
         &mut self,
         future: Box<vulkano::sync::GpuFuture>,
     ) -> (Grid, Box<vulkano::sync::GpuFuture>) {
-        let bbox = self.bbox_finder.calculate_bbox(self.queue.clone(), future);
-
-        let dx = bbox.max.position[0] - bbox.min.position[0];
-        let dy = bbox.max.position[1] - bbox.min.position[1];
-        let dz = bbox.max.position[2] - bbox.min.position[2];
-
-        let grid_size = [dx, dy, dz];
-        let resolution = calc_grid_reolution(&grid_size, self.triangle_count);
-        let cell_size = [
-            dx / resolution[0] as f32,
-            dy / resolution[1] as f32,
-            dz / resolution[2] as f32,
-        ];
-
-        let count_pairs_result = self.pair_counter.count_pairs(
+        let builder = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
+            self.queue.device().clone(),
+            self.queue.family(),
+        ).unwrap();
+        let (builder, bbox_buffer) = self.bbox_finder.calculate_bbox(builder);
+        let (builder, grid_params_buffer) =
+            self.grid_params_calculator
+                .calculate(builder, self.queue.clone(), bbox_buffer);
+        let (builder, counted_pairs) = self.pair_counter.count_pairs(
+            builder,
             self.queue.clone(),
-            bbox.min.position,
-            cell_size,
-            resolution,
+            grid_params_buffer.clone(),
+            self.max_cell_count,
         );
+        let command_buffer = builder.build().unwrap();
+
+        // the references are allocated by the pair count, the uniform grid waits only here
+        future
+            .then_execute(self.queue.clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let grid_params = *grid_params_buffer
+            .read()
+            .expect("failed to read grid params buffer");
+        let bbox = BBox {
+            min: Vec3::from_arr3(grid_params.min_cell),
+            max: Vec3::from_arr3(grid_params.max_cell),
+        };
+        let resolution = grid_params.resolution;
+        let cell_size = grid_params.cell_size;
+
+        let count_pairs_result = counted_pairs.into_result(self.queue.device().clone());
+        let pair_count = count_pairs_result.pair_count;
         let (cells_buffer, references_buffer, future) =
             self.pair_writer
                 .write_pairs(self.queue.clone(), count_pairs_result, resolution);
+        let top_level = TopLevel {
+            min_cell: bbox.min.position,
+            cell_size,
+            resolution,
+            cells_buffer,
+            references_buffer,
+            pair_count,
+        };
+
+        match self.kind {
+            GridKind::Uniform => {
+                // the tracer expects the top level cells even if there are no leaf grids
+                let (top_cells_buffer, top_cells_future) =
+                    vulkano::buffer::ImmutableBuffer::from_iter(
+                        iter::once([0u32; 4]),
+                        vulkano::buffer::BufferUsage::all(),
+                        self.queue.clone(),
+                    ).expect("failed to create top cells buffer");
+                (
+                    Grid {
+                        kind: self.kind,
+                        bbox,
+                        resolution,
+                        cell_size,
+                        cells_buffer: top_level.cells_buffer,
+                        references_buffer: top_level.references_buffer,
+                        top_cells_buffer,
+                        leaf_cell_count: 0,
+                    },
+                    Box::new(future.join(top_cells_future)),
+                )
+            }
+            GridKind::TwoLevel => {
+                let builder =
+                    vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
+                        self.queue.device().clone(),
+                        self.queue.family(),
+                    ).unwrap();
+                let (builder, top_cells) = self.leaf_resolution.calculate(
+                    builder,
+                    self.queue.clone(),
+                    &top_level,
+                    LEAF_DENSITY,
+                    MAX_LEAF_RESOLUTION,
+                );
+                let (builder, counted_pairs) = self.leaf_pair_counter.count_pairs(
+                    builder,
+                    self.queue.clone(),
+                    &top_level,
+                    &top_cells,
+                );
+                let command_buffer = builder.build().unwrap();
+
+                // the second wait, the leaf references are allocated by the leaf pair count
+                future
+                    .then_execute(self.queue.clone(), command_buffer)
+                    .unwrap()
+                    .then_signal_fence_and_flush()
+                    .unwrap()
+                    .wait(None)
+                    .unwrap();
+
+                let leaf_cell_count = top_cells
+                    .leaf_cell_count_buffer
+                    .read()
+                    .expect("failed to read leaf cell count buffer")[0]
+                    as usize;
+                let top_cells_buffer = top_cells.top_cells_buffer;
+                let count_pairs_result = counted_pairs.into_result(self.queue.device().clone());
+                let (cells_buffer, references_buffer, future) = self.leaf_pair_writer.write_pairs(
+                    self.queue.clone(),
+                    count_pairs_result,
+                    &top_level,
+                    top_cells_buffer.clone(),
+                    leaf_cell_count,
+                );
+                (
+                    Grid {
+                        kind: self.kind,
+                        bbox,
+                        resolution,
+                        cell_size,
+                        cells_buffer,
+                        references_buffer,
+                        top_cells_buffer,
+                        leaf_cell_count,
+                    },
+                    future,
+                )
+            }
+        }
+    }
+}
+
+/// Grid built on the GPU when the scene is loaded, which is traversed by the tracers
+pub struct GridStructure {
+    queue: Arc<vulkano::device::Queue>,
+    builder: GridBuilder,
+    /// `None` until the first build
+    grid: Option<Grid>,
+    uniform_buffer: Option<Arc<vulkano::buffer::BufferAccess + Send + Sync>>,
+}
+
+impl GridStructure {
+    pub fn new(
+        queue: Arc<vulkano::device::Queue>,
+        scene_buffers: &ModelBuffers,
+        kind: GridKind,
+    ) -> GridStructure {
+        let builder = GridBuilder::new(queue.clone(), scene_buffers, kind);
+        GridStructure {
+            queue,
+            builder,
+            grid: None,
+            uniform_buffer: None,
+        }
+    }
+
+    fn grid(&self) -> &Grid {
+        self.grid.as_ref().expect("grid isn't built")
+    }
+}
+
+impl AccelerationStructure for GridStructure {
+    fn name(&self) -> &'static str {
+        match self.builder.kind {
+            GridKind::Uniform => "Grid",
+            GridKind::TwoLevel => "Two-level grid",
+        }
+    }
+
+    fn build(&mut self, future: Box<vulkano::sync::GpuFuture>) -> Box<vulkano::sync::GpuFuture> {
+        let (grid, future) = self.builder.build(future);
+        let (uniform_buffer, uniform_future) = vulkano::buffer::ImmutableBuffer::from_data(
+            grid_uniform(&grid),
+            vulkano::buffer::BufferUsage::uniform_buffer(),
+            self.queue.clone(),
+        ).expect("failed to create grid uniform buffer");
+        self.grid = Some(grid);
+        self.uniform_buffer = Some(uniform_buffer);
+        Box::new(future.join(uniform_future))
+    }
 
-        (
-            Grid {
-                bbox,
-                resolution,
-                cell_size,
-                cells_buffer,
-                references_buffer,
-            },
-            future,
+    fn traversal(&self) -> &'static str {
+        "grid"
+    }
+
+    fn build_descriptor_set(
+        &self,
+        pipeline: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
+        set_id: usize,
+    ) -> Result<
+        Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+        vulkano::descriptor::descriptor_set::PersistentDescriptorSetError,
+    > {
+        let grid = self.grid();
+        let uniform_buffer = self.uniform_buffer.clone().expect("grid isn't built");
+        let ds = vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+            pipeline,
+            set_id,
+        ).add_buffer(uniform_buffer)?
+            .add_buffer(grid.cells_buffer.clone())?
+            .add_buffer(grid.references_buffer.clone())?
+            .add_buffer(grid.top_cells_buffer.clone())?
+            .build()
+            .expect("failed to build grid descriptor set");
+        Ok(Arc::new(ds))
+    }
+
+    fn write_statistics(
+        &self,
+        out: &mut fmt::Write,
+        statistics: &tracers::ty::Statistics,
+        primary_rays: u32,
+    ) -> fmt::Result {
+        let grid = self.grid();
+        let grid_size = [
+            grid.bbox.max.position[0] - grid.bbox.min.position[0],
+            grid.bbox.max.position[1] - grid.bbox.min.position[1],
+            grid.bbox.max.position[2] - grid.bbox.min.position[2],
+        ];
+        writeln!(out, "\tsize: {:?}", grid_size)?;
+        writeln!(out, "\tresolution: {:?}", grid.resolution)?;
+        let cell_count = grid.resolution[0] * grid.resolution[1] * grid.resolution[2];
+        writeln!(out, "\tcell count: {}", cell_count)?;
+        writeln!(out, "\tcell size: {:?}", grid.cell_size)?;
+        // rays intersect the leaf cells of the two-level grid
+        let cell_count = if grid.kind == GridKind::TwoLevel {
+            writeln!(out, "\tleaf cell count: {}", grid.leaf_cell_count)?;
+            grid.leaf_cell_count as u32
+        } else {
+            cell_count
+        };
+        writeln!(out, "\tcell intersections: {}", statistics.cell_intersections)?;
+        writeln!(
+            out,
+            "\tintersections per ray: {}",
+            statistics.cell_intersections as f32 / primary_rays as f32
+        )?;
+        writeln!(
+            out,
+            "\tintersections per cell: {}",
+            statistics.cell_intersections as f32 / cell_count as f32
         )
     }
 }
 
-fn calc_grid_reolution(grid_size: &[f32; 3], triangle_count: usize) -> [u32; 3] {
-    let volume = grid_size[0] * grid_size[1] * grid_size[2];
-    let k = (5.0 * triangle_count as f32 / volume).powf(1.0 / 3.0);
-    let nx = (grid_size[0] * k).floor().max(1.0) as u32;
-    let ny = (grid_size[1] * k).floor().max(1.0) as u32;
-    let nz = (grid_size[2] * k).floor().max(1.0) as u32;
-    [nx, ny, nz]
+/// Parameters of the traversal in the layout of the `Grid` block in accel/grid.glsl.tera
+fn grid_uniform(grid: &Grid) -> tracers::traversals::grid::ty::Grid {
+    tracers::traversals::grid::ty::Grid {
+        minimum_cell: grid.bbox.min.position,
+        maximum_cell: grid.bbox.max.position,
+        resolution: grid.resolution,
+        cell_size: grid.cell_size,
+        levels: match grid.kind {
+            GridKind::Uniform => 1,
+            GridKind::TwoLevel => 2,
+        },
+        _dummy0: [0; 4],
+        _dummy1: [0; 4],
+        _dummy2: [0; 4],
+    }
 }
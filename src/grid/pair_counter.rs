@@ -1,8 +1,8 @@
 extern crate vulkano;
 
-use gl_types::Vec3;
+use super::prefix_scan::PrefixScanner;
 
-use vulkano::sync::GpuFuture;
+use gl_types::Vec3;
 
 use std::iter;
 use std::sync::Arc;
@@ -20,7 +20,6 @@ mod count_pairs {
 pub struct PairCounter {
     pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
     input_ds: Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
-    uniform_buffer_pool: vulkano::buffer::CpuBufferPool<count_pairs::ty::Params>,
     output_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
         Arc<
             vulkano::pipeline::ComputePipeline<
@@ -28,10 +27,22 @@ pub struct PairCounter {
             >,
         >,
     >,
-    triangle_count: usize,
+    prefix_scanner: PrefixScanner,
+    /// Triangles and primitives, which are counted together
+    object_count: usize,
     work_groups_count: usize,
 }
 
+/// Buffers of the recorded pass, which are valid after the command buffer is executed
+pub struct CountedPairs {
+    /// Scanned counts of the pairs of every cell
+    pub cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub min_cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub max_cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Total count of the pairs, the only value read back by the host
+    pub pair_count_buffer: Arc<vulkano::buffer::CpuAccessibleBuffer<[u32]>>,
+}
+
 pub struct CountPairsResult {
     pub pair_count: usize,
     pub cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
@@ -40,12 +51,29 @@ pub struct CountPairsResult {
     pub max_cells_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
 }
 
+impl CountedPairs {
+    /// Must be called after the command buffer with the pass is executed
+    pub fn into_result(self, device: Arc<vulkano::device::Device>) -> CountPairsResult {
+        let pair_count = self.pair_count_buffer
+            .read()
+            .expect("failed to read pair count buffer")[0];
+        CountPairsResult {
+            pair_count: pair_count as usize,
+            cells_buffer: self.cells_buffer,
+            cells_buffer_future: Box::new(vulkano::sync::now(device)),
+            min_cells_buffer: self.min_cells_buffer,
+            max_cells_buffer: self.max_cells_buffer,
+        }
+    }
+}
+
 impl PairCounter {
     pub fn new(
         queue: Arc<vulkano::device::Queue>,
         positions: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
         indices: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
-        triangle_count: usize,
+        primitive_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        object_count: usize,
     ) -> PairCounter {
         let device = queue.device();
 
@@ -59,8 +87,8 @@ impl PairCounter {
             ).expect("failed to create compute pipeline")
         });
 
-        let work_groups_count = triangle_count / WORKGROUP_SIZE;
-        let work_groups_count = if triangle_count % WORKGROUP_SIZE == 0 {
+        let work_groups_count = object_count / WORKGROUP_SIZE;
+        let work_groups_count = if object_count % WORKGROUP_SIZE == 0 {
             work_groups_count
         } else {
             work_groups_count + 1
@@ -74,75 +102,66 @@ impl PairCounter {
                 .unwrap()
                 .add_buffer(indices)
                 .unwrap()
+                .add_buffer(primitive_bounds)
+                .unwrap()
                 .build()
                 .unwrap(),
         );
 
-        let uniform_buffer_pool = vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone());
         let output_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
             pipeline.clone(),
             1,
         );
+        let prefix_scanner = PrefixScanner::new(queue.clone());
 
         PairCounter {
             pipeline,
             input_ds,
-            uniform_buffer_pool,
             output_ds_pool,
-            triangle_count,
+            prefix_scanner,
+            object_count,
             work_groups_count,
         }
     }
 
+    /// Records counting of the pairs with the parameters written by the previous pass.
+    /// The resolution isn't known yet, so the cells buffer has room for `max_cell_count`.
     pub fn count_pairs(
         &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
         queue: Arc<vulkano::device::Queue>,
-        min_cell: [f32; 3],
-        cell_size: [f32; 3],
-        grid_resolution: [u32; 3],
-    ) -> CountPairsResult {
+        grid_params: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        max_cell_count: usize,
+    ) -> (vulkano::command_buffer::AutoCommandBufferBuilder, CountedPairs) {
         let device = queue.device();
 
-        let parameters = self.uniform_buffer_pool
-            .next(count_pairs::ty::Params {
-                min_cell,
-                cell_size,
-                resolution: grid_resolution,
-                _dummy0: [0; 4],
-                _dummy1: [0; 4],
-            })
-            .expect("failed to create parameters buffer");
-
-        let cell_count = grid_resolution[0] * grid_resolution[1] * grid_resolution[2];
-        let ref_buffer = {
-            // create one more cell so the last one contains total references count
-            let data_iter = (0..cell_count + 1).map(|_| 0u32);
-            vulkano::buffer::CpuAccessibleBuffer::from_iter(
-                device.clone(),
-                vulkano::buffer::BufferUsage::all(),
-                data_iter,
-            ).expect("failed to create cells buffer")
-        };
+        // create one more cell so the last one contains total references count
+        let cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            max_cell_count + 1,
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(queue.family()),
+        ).expect("failed to create cells buffer");
 
         let min_cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[Vec3]>::array(
-            queue.device().clone(),
-            self.triangle_count,
+            device.clone(),
+            self.object_count,
             vulkano::buffer::BufferUsage::all(),
             iter::once(queue.family()),
         ).expect("can't create references buffer");
 
         let max_cells_buffer = vulkano::buffer::DeviceLocalBuffer::<[Vec3]>::array(
-            queue.device().clone(),
-            self.triangle_count,
+            device.clone(),
+            self.object_count,
             vulkano::buffer::BufferUsage::all(),
             iter::once(queue.family()),
         ).expect("can't create references buffer");
 
         let output_ds = self.output_ds_pool
             .next()
-            .add_buffer(parameters)
+            .add_buffer(grid_params)
             .unwrap()
-            .add_buffer(ref_buffer.clone())
+            .add_buffer(cells_buffer.clone())
             .unwrap()
             .add_buffer(min_cells_buffer.clone())
             .unwrap()
@@ -151,48 +170,30 @@ impl PairCounter {
             .build()
             .unwrap();
 
-        let command_buffer =
-            vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
-                device.clone(),
-                queue.family(),
-            ).unwrap()
-                .dispatch(
-                    [self.work_groups_count as u32, 1, 1],
-                    self.pipeline.clone(),
-                    (self.input_ds.clone(), output_ds),
-                    (),
-                )
-                .unwrap()
-                .build()
-                .unwrap();
-
-        let future = vulkano::sync::now(device.clone())
-            .then_execute(queue.clone(), command_buffer)
+        let builder = builder
+            .fill_buffer(cells_buffer.clone(), 0)
             .unwrap()
-            .then_signal_fence_and_flush()
+            .dispatch(
+                [self.work_groups_count as u32, 1, 1],
+                self.pipeline.clone(),
+                (self.input_ds.clone(), output_ds),
+                (),
+            )
             .unwrap();
-        future.wait(None).unwrap();
-
-        let mut pair_count = 0;
-        let (cells_buffer, cells_future) = {
-            let lock = ref_buffer.read().expect("failed to read cells buffer");
-            let data_iter = lock.into_iter().map(|size| {
-                let prev_count = pair_count;
-                pair_count += size;
-                prev_count
-            });
-            vulkano::buffer::ImmutableBuffer::from_iter(
-                data_iter,
-                vulkano::buffer::BufferUsage::all(),
-                queue.clone(),
-            ).expect("failed to create references buffer")
-        };
-        CountPairsResult {
-            pair_count: pair_count as usize,
-            cells_buffer,
-            cells_buffer_future: Box::new(cells_future),
-            min_cells_buffer,
-            max_cells_buffer,
-        }
+
+        // cells past the resolution stay empty, so they don't change the total count
+        let (builder, pair_count_buffer) =
+            self.prefix_scanner
+                .scan(builder, queue.clone(), cells_buffer.clone(), max_cell_count + 1);
+
+        (
+            builder,
+            CountedPairs {
+                cells_buffer,
+                min_cells_buffer,
+                max_cells_buffer,
+                pair_count_buffer,
+            },
+        )
     }
 }
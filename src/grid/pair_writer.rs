@@ -31,7 +31,7 @@ pub struct PairWriter {
 }
 
 impl PairWriter {
-    pub fn new(queue: Arc<vulkano::device::Queue>, triangle_count: usize) -> PairWriter {
+    pub fn new(queue: Arc<vulkano::device::Queue>, object_count: usize) -> PairWriter {
         let device = queue.device();
 
         let pipeline = Arc::new({
@@ -44,8 +44,8 @@ impl PairWriter {
             ).expect("failed to create compute pipeline")
         });
 
-        let work_groups_count = triangle_count / WORKGROUP_SIZE;
-        let work_groups_count = if triangle_count % WORKGROUP_SIZE == 0 {
+        let work_groups_count = object_count / WORKGROUP_SIZE;
+        let work_groups_count = if object_count % WORKGROUP_SIZE == 0 {
             work_groups_count
         } else {
             work_groups_count + 1
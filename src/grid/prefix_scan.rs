@@ -0,0 +1,189 @@
+extern crate vulkano;
+
+use std::iter;
+use std::sync::Arc;
+
+const WORKGROUP_SIZE: usize = 256;
+/// Every work group of scan_blocks.comp scans two values per invocation
+const BLOCK_SIZE: usize = 2 * WORKGROUP_SIZE;
+
+mod scan_blocks {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/scan_blocks.comp"]
+    struct Dummy;
+}
+
+mod scan_add {
+    #![allow(dead_code)]
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[path = "shaders/scan_add.comp"]
+    struct Dummy;
+}
+
+/// Exclusive prefix sum on the GPU: blocks are scanned separately, sums of the blocks
+/// are scanned the same way and added back to the blocks.
+pub struct PrefixScanner {
+    scan_pipeline: Arc<
+        vulkano::pipeline::ComputePipeline<
+            vulkano::descriptor::pipeline_layout::PipelineLayout<scan_blocks::Layout>,
+        >,
+    >,
+    add_pipeline: Arc<
+        vulkano::pipeline::ComputePipeline<
+            vulkano::descriptor::pipeline_layout::PipelineLayout<scan_add::Layout>,
+        >,
+    >,
+    scan_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<scan_blocks::Layout>,
+            >,
+        >,
+    >,
+    add_ds_pool: vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool<
+        Arc<
+            vulkano::pipeline::ComputePipeline<
+                vulkano::descriptor::pipeline_layout::PipelineLayout<scan_add::Layout>,
+            >,
+        >,
+    >,
+}
+
+impl PrefixScanner {
+    pub fn new(queue: Arc<vulkano::device::Queue>) -> PrefixScanner {
+        let device = queue.device();
+
+        let scan_pipeline = Arc::new({
+            let shader =
+                scan_blocks::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+        let add_pipeline = Arc::new({
+            let shader =
+                scan_add::Shader::load(device.clone()).expect("failed to create shader module");
+            vulkano::pipeline::ComputePipeline::new(
+                device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline")
+        });
+
+        let scan_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            scan_pipeline.clone(),
+            0,
+        );
+        let add_ds_pool = vulkano::descriptor::descriptor_set::FixedSizeDescriptorSetsPool::new(
+            add_pipeline.clone(),
+            0,
+        );
+
+        PrefixScanner {
+            scan_pipeline,
+            add_pipeline,
+            scan_ds_pool,
+            add_ds_pool,
+        }
+    }
+
+    /// Records the scan of the first `count` values of `data` in place, the returned buffer
+    /// contains the total sum after the command buffer is executed
+    pub fn scan(
+        &mut self,
+        builder: vulkano::command_buffer::AutoCommandBufferBuilder,
+        queue: Arc<vulkano::device::Queue>,
+        data: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        count: usize,
+    ) -> (
+        vulkano::command_buffer::AutoCommandBufferBuilder,
+        Arc<vulkano::buffer::CpuAccessibleBuffer<[u32]>>,
+    ) {
+        let device = queue.device();
+        let total_buffer = vulkano::buffer::CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            vulkano::buffer::BufferUsage::all(),
+            iter::once(0u32),
+        ).expect("failed to create total sum buffer");
+
+        // every level contains the sums of the blocks of the previous one,
+        // the last level is a single block, which sum is the total one
+        let mut levels = vec![(data, count)];
+        let mut builder = builder;
+        loop {
+            let (level_buffer, level_count) = {
+                let level = levels.last().unwrap();
+                (level.0.clone(), level.1)
+            };
+            let block_count = blocks_count(level_count, BLOCK_SIZE);
+            let sums_buffer: Arc<vulkano::buffer::BufferAccess + Send + Sync> =
+                if block_count == 1 {
+                    total_buffer.clone()
+                } else {
+                    vulkano::buffer::DeviceLocalBuffer::<[u32]>::array(
+                        device.clone(),
+                        block_count,
+                        vulkano::buffer::BufferUsage::all(),
+                        iter::once(queue.family()),
+                    ).expect("failed to create block sums buffer")
+                };
+            let descriptor_set = self.scan_ds_pool
+                .next()
+                .add_buffer(level_buffer)
+                .unwrap()
+                .add_buffer(sums_buffer.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+            builder = builder
+                .dispatch(
+                    [block_count as u32, 1, 1],
+                    self.scan_pipeline.clone(),
+                    descriptor_set,
+                    (),
+                )
+                .unwrap();
+            if block_count == 1 {
+                break;
+            }
+            levels.push((sums_buffer, block_count));
+        }
+
+        // scanned sums are added from the top level down to the data
+        for pair in levels.windows(2).rev() {
+            let (ref level_buffer, level_count) = pair[0];
+            let descriptor_set = self.add_ds_pool
+                .next()
+                .add_buffer(level_buffer.clone())
+                .unwrap()
+                .add_buffer(pair[1].0.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+            builder = builder
+                .dispatch(
+                    [blocks_count(level_count, WORKGROUP_SIZE) as u32, 1, 1],
+                    self.add_pipeline.clone(),
+                    descriptor_set,
+                    (),
+                )
+                .unwrap();
+        }
+
+        (builder, total_buffer)
+    }
+}
+
+fn blocks_count(count: usize, block_size: usize) -> usize {
+    let blocks_count = count / block_size;
+    if count % block_size == 0 {
+        blocks_count.max(1)
+    } else {
+        blocks_count + 1
+    }
+}
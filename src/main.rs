@@ -1,6 +1,7 @@
 #![feature(attr_literals)]
 
 extern crate cgmath;
+extern crate gltf;
 extern crate time;
 extern crate tobj;
 extern crate winit;
@@ -17,11 +18,17 @@ extern crate clap;
 #[macro_use]
 extern crate lazy_static;
 extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 
+mod accel;
 mod args;
 mod control;
 mod tracers;
 mod render;
+mod bvh;
 mod fps_counter;
 mod gl_types;
 mod grid;
@@ -72,6 +79,13 @@ fn print_message_callback(msg: &vulkano::instance::debug::Message) {
     println!("{} [{}] : {}", msg.layer_prefix, ty, msg.description);
 }
 
+fn exit_with_error(error: &scene::SceneError) -> ! {
+    use std::error::Error;
+    eprintln!("Failed to load the scene ({})", error.description());
+    eprintln!("{}", error);
+    std::process::exit(1);
+}
+
 fn main() {
     let args = Args::get_matches();
     let extensions = vulkano::instance::InstanceExtensions {
@@ -89,10 +103,17 @@ fn main() {
         print_message_callback,
     ).ok();
 
-    let mut camera = control::Camera::with_position(args.position, args.fov);
+    let mut camera = control::Camera::new(
+        args.position,
+        args.yaw.to_radians(),
+        args.pitch.to_radians(),
+        args.fov,
+    );
 
     if args.benchmark {
-        let mut render = OfflineRender::new(&args, &instance, [args.resolution[0], args.resolution[1]]);
+        let mut render =
+            OfflineRender::new(&args, &instance, [args.resolution[0], args.resolution[1]])
+                .unwrap_or_else(|e| exit_with_error(&e));
         let statistics = render.render(&camera);
         println!("=============== Statistics ===============");
         println!("{}", statistics);
@@ -103,7 +124,8 @@ fn main() {
             args.fps_update_interval,
         ));
 
-        let mut render = RealTimeRender::new(&args, &events_loop, &instance);
+        let mut render = RealTimeRender::new(&args, &events_loop, &instance)
+            .unwrap_or_else(|e| exit_with_error(&e));
         let mut previous_frame_end =
             Box::new(vulkano::sync::now(render.vulkan_ctx.device.clone())) as Box<_>;
 
@@ -7,11 +7,10 @@ use super::vulkan_ctx::VulkanCtx;
 
 use args::Args;
 use control::Camera;
+use scene::SceneError;
 use tracers;
-use grid::Grid;
 
 use std::mem;
-use std::path::Path;
 use std::sync::Arc;
 use std::fmt;
 
@@ -27,9 +26,9 @@ impl<'a> OfflineRender<'a> {
         args: &Args,
         instance: &'a Arc<vulkano::instance::Instance>,
         dimensions: [u32; 2],
-    ) -> OfflineRender<'a> {
+    ) -> Result<OfflineRender<'a>, SceneError> {
         let (vulkan_ctx, _) =
-            VulkanCtx::new(&instance, Path::new(&args.model), |&q| q.supports_compute());
+            VulkanCtx::new(&instance, &args.scene, args.acceleration, |&q| q.supports_compute())?;
         let statistics_buffer =
             vulkano::buffer::CpuAccessibleBuffer::<tracers::ty::Statistics>::from_data(
                 vulkan_ctx.device.clone(),
@@ -38,6 +37,7 @@ impl<'a> OfflineRender<'a> {
                     triangle_intersections: 0,
                     triangle_tests: 0,
                     cell_intersections: 0,
+                    node_intersections: 0,
                 },
             ).unwrap();
 
@@ -51,21 +51,21 @@ impl<'a> OfflineRender<'a> {
             Some(vulkan_ctx.queue.family()),
         ).unwrap();
 
-        OfflineRender {
+        Ok(OfflineRender {
             vulkan_ctx,
             statistics_buffer,
             texture,
             dimensions,
-        }
+        })
     }
 
     pub fn render(&mut self, camera: &Camera) -> Statistics {
-        let grid_start = time::PreciseTime::now();
-        let (grid, future) = self.vulkan_ctx
-            .grid_builder
+        let build_start = time::PreciseTime::now();
+        let future = self.vulkan_ctx
+            .acceleration
             .build(Box::new(vulkano::sync::now(self.vulkan_ctx.device.clone())));
         mem::drop(future);
-        let grid_build_time = grid_start.to(time::PreciseTime::now()).num_milliseconds();
+        let build_time = build_start.to(time::PreciseTime::now()).num_milliseconds();
 
         let cb = {
             let mut cbb =
@@ -79,7 +79,7 @@ impl<'a> OfflineRender<'a> {
                 self.texture.clone(),
                 self.statistics_buffer.clone(),
                 &camera,
-                &grid,
+                &*self.vulkan_ctx.acceleration,
             );
 
             cbb.build().unwrap()
@@ -99,29 +99,39 @@ impl<'a> OfflineRender<'a> {
             .expect("failed to lock buffer for reading");
 
         let primary_rays = self.dimensions[0] * self.dimensions[1];
+        let mut accel_statistics = String::new();
+        self.vulkan_ctx
+            .acceleration
+            .write_statistics(&mut accel_statistics, &render_statistics, primary_rays)
+            .expect("failed to write acceleration structure statistics");
         Statistics {
-            grid_build_time,
+            build_time,
             render_time,
             triangle_count: self.vulkan_ctx.scene_buffers.triangle_count,
+            model_count: self.vulkan_ctx.scene_buffers.model_count,
             primary_rays,
             render_statistics,
-            grid,
+            accel_name: self.vulkan_ctx.acceleration.name(),
+            accel_statistics,
         }
     }
 }
 
 pub struct Statistics {
-    grid_build_time: i64,
+    build_time: i64,
     render_time: i64,
     triangle_count: usize,
+    model_count: usize,
     primary_rays: u32,
     render_statistics: tracers::ty::Statistics,
-    grid: Grid,
+    accel_name: &'static str,
+    /// Written by the acceleration structure, which can be rebuilt before they are printed
+    accel_statistics: String,
 }
 
 impl fmt::Display for Statistics {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let render_time = self.grid_build_time + self.render_time;
+        let render_time = self.build_time + self.render_time;
         writeln!(f, "\n>>> General")?;
         writeln!(
             f,
@@ -134,6 +144,7 @@ impl fmt::Display for Statistics {
             "\ttriangles: {}",
             self.triangle_count
         )?;
+        writeln!(f, "\tmodels: {}", self.model_count)?;
         writeln!(f, "\tprimary rays: {}", self.primary_rays)?;
         writeln!(f, "\n>>> Triangle")?;
         writeln!(f, "\ttests: {}", self.render_statistics.triangle_tests)?;
@@ -148,28 +159,8 @@ impl fmt::Display for Statistics {
             "\ttests per triangle: {}",
             self.render_statistics.triangle_tests as f32 / self.triangle_count as f32
         )?;
-        writeln!(f, "\n>>> Grid")?;
-        writeln!(f, "\tbuild time: {} ms", self.grid_build_time)?;
-        let grid_size = [
-            self.grid.bbox.max.position[0] - self.grid.bbox.min.position[0],
-            self.grid.bbox.max.position[1] - self.grid.bbox.min.position[1],
-            self.grid.bbox.max.position[2] - self.grid.bbox.min.position[2],
-        ];
-        writeln!(f, "\tsize: {:?}", grid_size)?;
-        writeln!(f, "\tresolution: {:?}", self.grid.resolution)?;
-        let cell_count = self.grid.resolution[0] * self.grid.resolution[1] * self.grid.resolution[2];
-        writeln!(f, "\tcell count: {}", cell_count)?;
-        writeln!(f, "\tcell size: {:?}", self.grid.cell_size)?;
-        writeln!(f, "\tcell intersections: {}", self.render_statistics.cell_intersections)?;
-        writeln!(
-            f,
-            "\tintersections per ray: {}",
-            self.render_statistics.cell_intersections as f32 / self.primary_rays as f32
-        )?;
-        writeln!(
-            f,
-            "\tintersections per cell: {}",
-            self.render_statistics.cell_intersections as f32 / cell_count as f32
-        )
+        writeln!(f, "\n>>> {}", self.accel_name)?;
+        writeln!(f, "\tbuild time: {} ms", self.build_time)?;
+        write!(f, "{}", self.accel_statistics)
     }
 }
@@ -12,14 +12,15 @@ use args::Args;
 use control;
 use tracers;
 use fps_counter::FPSCounter;
+use scene::{self, SceneError};
 
-use std::path::Path;
 use std::sync::Arc;
 
 pub struct RealTimeRender<'a> {
     pub vulkan_ctx: VulkanCtx<'a>,
     window: vulkano_win::Window,
     drawer: Drawer<'a>,
+    scene_watcher: scene::SceneWatcher,
 }
 
 impl<'a> RealTimeRender<'a> {
@@ -27,7 +28,7 @@ impl<'a> RealTimeRender<'a> {
         args: &Args,
         events_loop: &winit::EventsLoop,
         instance: &'a Arc<vulkano::instance::Instance>,
-    ) -> RealTimeRender<'a> {
+    ) -> Result<RealTimeRender<'a>, SceneError> {
         let window = winit::WindowBuilder::new()
             .with_min_dimensions(args.resolution[0], args.resolution[1])
             .with_max_dimensions(args.resolution[0], args.resolution[1])
@@ -35,9 +36,9 @@ impl<'a> RealTimeRender<'a> {
             .unwrap();
         window.window().set_cursor(winit::MouseCursor::NoneCursor);
 
-        let (vulkan_ctx, _) = VulkanCtx::new(&instance, Path::new(&args.model), |&q| {
+        let (vulkan_ctx, _) = VulkanCtx::new(&instance, &args.scene, args.acceleration, |&q| {
             q.supports_graphics() && window.surface().is_supported(q).unwrap_or(false)
-        });
+        })?;
 
         let (drawer, _) = Drawer::new(
             vulkan_ctx.device.clone(),
@@ -46,11 +47,14 @@ impl<'a> RealTimeRender<'a> {
             vulkan_ctx.queue.clone(),
         );
 
-        RealTimeRender {
+        let scene_watcher = scene::SceneWatcher::new(args.scene.clone(), &vulkan_ctx.scene_files);
+
+        Ok(RealTimeRender {
             vulkan_ctx,
             window,
             drawer,
-        }
+            scene_watcher,
+        })
     }
 
     pub fn render(
@@ -62,6 +66,14 @@ impl<'a> RealTimeRender<'a> {
     ) -> Box<vulkano::sync::GpuFuture> {
         previous_frame_end.cleanup_finished();
 
+        // the camera is not a part of the scene, so it stays where it was
+        if let Some(result) = self.scene_watcher.poll() {
+            match result.and_then(|loaded| self.vulkan_ctx.reload_scene(loaded)) {
+                Ok(()) => println!("scene reloaded"),
+                Err(e) => println!("failed to reload the scene: {}", e),
+            }
+        }
+
         if self.drawer.recreate_swapchain(&self.window) {
             return previous_frame_end;
         }
@@ -76,9 +88,9 @@ impl<'a> RealTimeRender<'a> {
             Err(err) => panic!("{:?}", err),
         };
 
-        let (grid, grid_future) = self.vulkan_ctx
-            .grid_builder
-            .build(Box::new(vulkano::sync::now(self.vulkan_ctx.device.clone())));
+        let device = self.vulkan_ctx.device.clone();
+        let (accel_future, accel_rebuilt) = self.vulkan_ctx
+            .update_acceleration(Box::new(vulkano::sync::now(device)));
 
         // FIXME: it is not used here, but is required for tracer.render()
         let statistics_buffer =
@@ -89,6 +101,7 @@ impl<'a> RealTimeRender<'a> {
                     triangle_intersections: 0,
                     triangle_tests: 0,
                     cell_intersections: 0,
+                    node_intersections: 0,
                 },
             ).unwrap();
 
@@ -103,7 +116,7 @@ impl<'a> RealTimeRender<'a> {
                 self.drawer.texture.clone(),
                 statistics_buffer.clone(),
                 &camera,
-                &grid,
+                &*self.vulkan_ctx.acceleration,
             );
             cbb = self.drawer.draw(cbb, image_num);
             cbb.build().unwrap()
@@ -113,7 +126,7 @@ impl<'a> RealTimeRender<'a> {
 
         let future = previous_frame_end
             .join(aquire_future)
-            .join(grid_future)
+            .join(accel_future)
             .then_execute(self.vulkan_ctx.queue.clone(), cb)
             .unwrap()
             .then_swapchain_present(
@@ -129,10 +142,12 @@ impl<'a> RealTimeRender<'a> {
             20.0,
             20.0,
             &format!(
-                "Using device: {}\nRender time: {} ms ({} FPS)\nCamera: {}",
+                "Using device: {}\nRender time: {} ms ({} FPS)\n{}: {}\nCamera: {}",
                 self.vulkan_ctx.physical.name(),
                 fps_counter.average_render_time(),
                 fps_counter.current_fps(),
+                self.vulkan_ctx.acceleration.name(),
+                if accel_rebuilt { "rebuilt" } else { "reused" },
                 camera
             ),
         );
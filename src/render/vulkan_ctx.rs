@@ -1,9 +1,11 @@
 extern crate vulkano;
 
-use grid;
+use vulkano::sync::GpuFuture;
+
+use accel;
 use scene;
 
-use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tracers::{RaycastingShader, Tracer};
 
@@ -12,16 +14,25 @@ pub struct VulkanCtx<'a> {
     pub device: Arc<vulkano::device::Device>,
     pub queue: Arc<vulkano::device::Queue>,
     pub scene_buffers: scene::ModelBuffers,
-    pub grid_builder: grid::GridBuilder,
+    pub acceleration: Box<accel::AccelerationStructure>,
+    accel_kind: accel::AccelerationKind,
+    /// The scene was reloaded since the acceleration structure was built, its geometry
+    /// doesn't change otherwise
+    scene_dirty: bool,
+    /// Upload of the reloaded scene, the next build of the structure waits for it
+    load_future: Option<Box<vulkano::sync::GpuFuture>>,
     pub tracer: Tracer<RaycastingShader>,
+    /// Files the scene was loaded from
+    pub scene_files: Vec<PathBuf>,
 }
 
 impl<'a> VulkanCtx<'a> {
     pub fn new<P>(
         instance: &'a Arc<vulkano::instance::Instance>,
-        model_path: &Path,
+        scene: &scene::SceneDescription,
+        accel_kind: accel::AccelerationKind,
         predicate: P,
-    ) -> (VulkanCtx<'a>, Box<vulkano::sync::GpuFuture>)
+    ) -> Result<(VulkanCtx<'a>, Box<vulkano::sync::GpuFuture>), scene::SceneError>
     where
         for<'r> P: FnMut(&'r vulkano::instance::QueueFamily) -> bool,
     {
@@ -49,29 +60,81 @@ impl<'a> VulkanCtx<'a> {
         ).expect("failed to create device");
         let queue = queues.next().unwrap();
 
+        let loaded_scene = scene::load_scene(scene)?;
+        let scene_files = loaded_scene.files.clone();
         let (scene_buffers, load_future) =
-            scene::ModelBuffers::from_obj(model_path, device.clone(), queue.clone())
-                .expect("failed to load model");
+            scene::ModelBuffers::from_loaded(loaded_scene, device.clone(), queue.clone())?;
 
-        let tracer = Tracer::new(device.clone(), &scene_buffers, RaycastingShader {}).unwrap();
+        let acceleration = accel::new_structure(queue.clone(), &scene_buffers, accel_kind);
 
-        let grid_builder = grid::GridBuilder::new(
-            queue.clone(),
-            scene_buffers.positions.clone(),
-            scene_buffers.indices.clone(),
-            scene_buffers.triangle_count,
-        );
+        let tracer = Tracer::new(
+            device.clone(),
+            &scene_buffers,
+            acceleration.traversal(),
+            RaycastingShader {},
+        ).unwrap();
 
-        (
+        Ok((
             VulkanCtx {
                 physical,
                 device,
                 queue,
                 scene_buffers,
-                grid_builder,
+                acceleration,
+                accel_kind,
+                scene_dirty: true,
+                load_future: None,
                 tracer,
+                scene_files,
             },
             load_future,
-        )
+        ))
+    }
+
+    /// Uploads the reloaded scene and uses it instead of the current one,
+    /// the acceleration structure is rebuilt after the upload by `update_acceleration`
+    pub fn reload_scene(
+        &mut self,
+        loaded_scene: scene::LoadedScene,
+    ) -> Result<(), scene::SceneError> {
+        let scene_files = loaded_scene.files.clone();
+        let (scene_buffers, load_future) = scene::ModelBuffers::from_loaded(
+            loaded_scene,
+            self.device.clone(),
+            self.queue.clone(),
+        )?;
+        self.tracer
+            .set_scene(&scene_buffers)
+            .expect("failed to build scene descriptor set");
+        self.acceleration =
+            accel::new_structure(self.queue.clone(), &scene_buffers, self.accel_kind);
+        self.scene_buffers = scene_buffers;
+        self.scene_files = scene_files;
+        self.scene_dirty = true;
+        // the previous upload is replaced, but the build still has to wait for it
+        let load_future: Box<vulkano::sync::GpuFuture> = match self.load_future.take() {
+            Some(previous_future) => Box::new(previous_future.join(load_future)),
+            None => load_future,
+        };
+        self.load_future = Some(load_future);
+        Ok(())
+    }
+
+    /// Rebuilds the acceleration structure after `future` and the upload of the reloaded
+    /// scene if the scene is dirty, otherwise the structure of the previous frames is reused.
+    /// Returns the future of the build and whether it happened.
+    pub fn update_acceleration(
+        &mut self,
+        future: Box<vulkano::sync::GpuFuture>,
+    ) -> (Box<vulkano::sync::GpuFuture>, bool) {
+        let future: Box<vulkano::sync::GpuFuture> = match self.load_future.take() {
+            Some(load_future) => Box::new(future.join(load_future)),
+            None => future,
+        };
+        if !self.scene_dirty {
+            return (future, false);
+        }
+        self.scene_dirty = false;
+        (self.acceleration.build(future), true)
     }
 }
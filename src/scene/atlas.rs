@@ -0,0 +1,188 @@
+extern crate image;
+
+use tracers;
+
+use std::cmp;
+
+/// Number of texture pages bound to the tracer shader, must match `tracer.comp.tera`
+pub const MAX_PAGES: usize = 16;
+/// Border around every texture filled with its edge pixels,
+/// so bilinear filtering doesn't bleed colors of the neighbours
+const PADDING: u32 = 2;
+
+/// All textures of the scene packed into at most `MAX_PAGES` images.
+/// Texture with index `i` occupies `rects[i]` (in normalized coordinates)
+/// of the page `rects[i].page`.
+pub struct Atlas {
+    pub pages: Vec<image::RgbaImage>,
+    /// Pages with the data of the linear textures, e.g. normal maps, which are not sRGB.
+    /// Linear and color textures don't share pages, so they are uploaded in their formats.
+    pub linear: Vec<bool>,
+    pub rects: Vec<tracers::ty::TextureRect>,
+}
+
+struct Placement {
+    page: usize,
+    x: u32,
+    y: u32,
+}
+
+impl Atlas {
+    /// `linear[i]` tells if the texture `i` is linear
+    pub fn new(mut textures: Vec<image::RgbaImage>, linear: &[bool], max_page_size: u32) -> Atlas {
+        if textures.is_empty() {
+            return Atlas {
+                pages: Vec::new(),
+                linear: Vec::new(),
+                rects: Vec::new(),
+            };
+        }
+
+        // the smallest page that fits the largest texture, doubled while
+        // textures of every kind don't fit into a single page
+        let kind_count = if linear.contains(&true) && linear.contains(&false) {
+            2
+        } else {
+            1
+        };
+        let largest_side = textures
+            .iter()
+            .map(|t| cmp::max(t.width(), t.height()))
+            .max()
+            .unwrap();
+        let mut page_size = cmp::min(
+            (largest_side + 2 * PADDING).next_power_of_two(),
+            max_page_size,
+        );
+        let (placements, page_heights, page_linear) = loop {
+            for texture in textures.iter_mut() {
+                fit_into(texture, page_size);
+            }
+            let (placements, page_heights, page_linear) = pack(&textures, linear, page_size);
+            if page_heights.len() == kind_count || page_size >= max_page_size {
+                if page_heights.len() <= MAX_PAGES {
+                    break (placements, page_heights, page_linear);
+                }
+                // even the largest pages are not enough, so sacrifice the quality
+                println!(
+                    "textures don't fit into {} pages of {}x{}, downscaling them",
+                    MAX_PAGES,
+                    page_size,
+                    page_size
+                );
+                for texture in textures.iter_mut() {
+                    let (width, height) = texture.dimensions();
+                    *texture = image::imageops::resize(
+                        texture,
+                        cmp::max(width / 2, 1),
+                        cmp::max(height / 2, 1),
+                        image::FilterType::Triangle,
+                    );
+                }
+            } else {
+                page_size = cmp::min(page_size * 2, max_page_size);
+            }
+        };
+
+        let mut pages: Vec<_> = page_heights
+            .iter()
+            .map(|&height| image::RgbaImage::new(page_size, height))
+            .collect();
+        let mut rects = Vec::with_capacity(textures.len());
+        for (texture, placement) in textures.iter().zip(placements.iter()) {
+            let page = &mut pages[placement.page];
+            copy_with_padding(texture, page, placement.x, placement.y);
+            let (page_width, page_height) = (page.width() as f32, page.height() as f32);
+            rects.push(tracers::ty::TextureRect {
+                offset: [
+                    (placement.x + PADDING) as f32 / page_width,
+                    (placement.y + PADDING) as f32 / page_height,
+                ],
+                size: [
+                    texture.width() as f32 / page_width,
+                    texture.height() as f32 / page_height,
+                ],
+                page: placement.page as u32,
+                _dummy0: [0; 12],
+            });
+        }
+
+        Atlas {
+            pages,
+            linear: page_linear,
+            rects,
+        }
+    }
+}
+
+/// Downscales the texture if it is larger than the page
+fn fit_into(texture: &mut image::RgbaImage, page_size: u32) {
+    let max_side = page_size - 2 * PADDING;
+    let (width, height) = texture.dimensions();
+    if width <= max_side && height <= max_side {
+        return;
+    }
+    let scale = max_side as f32 / cmp::max(width, height) as f32;
+    *texture = image::imageops::resize(
+        texture,
+        cmp::max((width as f32 * scale) as u32, 1),
+        cmp::max((height as f32 * scale) as u32, 1),
+        image::FilterType::Triangle,
+    );
+}
+
+/// Shelf packing: textures sorted by height are placed left to right
+/// in rows, new row starts above the highest texture of the previous one.
+/// Color textures come first and linear ones start a new page.
+/// Returns placements in the original order, heights of the used pages
+/// and if they are linear.
+fn pack(
+    textures: &[image::RgbaImage],
+    linear: &[bool],
+    page_size: u32,
+) -> (Vec<Placement>, Vec<u32>, Vec<bool>) {
+    let mut order: Vec<_> = (0..textures.len()).collect();
+    order.sort_by_key(|&i| (linear[i], cmp::Reverse(textures[i].height())));
+
+    let mut placements: Vec<Option<Placement>> = (0..textures.len()).map(|_| None).collect();
+    let mut page_heights = vec![0];
+    let mut page_linear = vec![linear[order[0]]];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let width = textures[i].width() + 2 * PADDING;
+        let height = textures[i].height() + 2 * PADDING;
+        if x + width > page_size {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if y + height > page_size || linear[i] != page_linear[page_linear.len() - 1] {
+            page_heights.push(0);
+            page_linear.push(linear[i]);
+            x = 0;
+            y = 0;
+            shelf_height = 0;
+        }
+        let page = page_heights.len() - 1;
+        placements[i] = Some(Placement { page, x, y });
+        x += width;
+        shelf_height = cmp::max(shelf_height, height);
+        page_heights[page] = cmp::max(page_heights[page], y + height);
+    }
+    (
+        placements.into_iter().map(|p| p.unwrap()).collect(),
+        page_heights,
+        page_linear,
+    )
+}
+
+fn copy_with_padding(texture: &image::RgbaImage, page: &mut image::RgbaImage, x: u32, y: u32) {
+    let (width, height) = texture.dimensions();
+    for py in 0..height + 2 * PADDING {
+        let ty = cmp::min(py.saturating_sub(PADDING), height - 1);
+        for px in 0..width + 2 * PADDING {
+            let tx = cmp::min(px.saturating_sub(PADDING), width - 1);
+            page.put_pixel(x + px, y + py, *texture.get_pixel(tx, ty));
+        }
+    }
+}
@@ -0,0 +1,386 @@
+extern crate byteorder;
+extern crate image;
+
+use self::byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::description::{ModelDescription, NormalsMode, UpAxis};
+use super::validate::Report;
+use super::{LoadedModel, Mesh, Model};
+use tracers;
+
+use std::env;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"VRTCACHE";
+/// Must be incremented whenever the layout of the cache file
+/// or the processing of loaded models changes
+const VERSION: u32 = 5;
+
+/// Identifies cache file of the model loaded with particular options
+pub struct Key {
+    source: PathBuf,
+    options_hash: u64,
+    path: PathBuf,
+}
+
+impl Key {
+    pub fn new(model: &ModelDescription, search_paths: &[PathBuf]) -> Key {
+        let source = fs::canonicalize(&model.path).unwrap_or_else(|_| model.path.clone());
+        // the fields are hashed as bytes, so the hash doesn't depend on the `Hash` impls
+        let mut hasher = FnvHasher::new();
+        write_path(&mut hasher, &source);
+        hasher.write_u8(match model.normals {
+            NormalsMode::Flat => 0,
+            NormalsMode::Smooth => 1,
+        });
+        hasher.write_u32(model.crease_angle.to_bits());
+        hasher.write_u8(model.repair as u8);
+        hasher.write_u8(match model.up_axis {
+            UpAxis::Y => 0,
+            UpAxis::Z => 1,
+        });
+        hasher.write_u32(model.unit_scale.to_bits());
+        hasher.write_u8(model.recenter as u8);
+        hasher.write_u32(search_paths.len() as u32);
+        for path in search_paths {
+            write_path(&mut hasher, path);
+        }
+        let options_hash = hasher.finish();
+        let path = cache_dir().join(format!("{:016x}.cache", options_hash));
+        Key {
+            source,
+            options_hash,
+            path,
+        }
+    }
+}
+
+/// 64-bit FNV-1a, which gives the same hashes in every build unlike `DefaultHasher`,
+/// so the caches written by the previous builds are still found and checked
+struct FnvHasher(u64);
+
+impl FnvHasher {
+    fn new() -> FnvHasher {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// The length goes first, so the consecutive paths don't run into each other
+fn write_path(hasher: &mut FnvHasher, path: &Path) {
+    let path = path.to_string_lossy();
+    hasher.write_u32(path.len() as u32);
+    hasher.write(path.as_bytes());
+}
+
+/// Per-user cache directory: `XDG_CACHE_HOME`, `LOCALAPPDATA` or `~/.cache`.
+/// Other users can't put their files there, unlike in the shared temporary directory.
+/// Without any of them the cache is kept in the target directory of the build.
+fn cache_dir() -> PathBuf {
+    let from_env = |name| match env::var_os(name) {
+        Some(ref dir) if Path::new(dir).is_absolute() => Some(PathBuf::from(dir)),
+        _ => None,
+    };
+    from_env("XDG_CACHE_HOME")
+        .or_else(|| from_env("LOCALAPPDATA"))
+        .or_else(|| from_env("HOME").map(|home| home.join(".cache")))
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("target"))
+        .join("vulkano-raytracing")
+}
+
+/// Modification time of the file, stored as seconds and nanoseconds since the epoch
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Timestamp(u64, u32);
+
+impl Timestamp {
+    fn of(path: &Path) -> io::Result<Timestamp> {
+        let modified = fs::metadata(path)?.modified()?;
+        let since_epoch = modified
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Ok(Timestamp(since_epoch.as_secs(), since_epoch.subsec_nanos()))
+    }
+}
+
+/// Returns `None` if there is no cache or the model or its dependencies changed since
+/// the cache was written. The model is considered unchanged if its modification time
+/// or content hash is the same, so touching the file doesn't invalidate the cache.
+/// Only dependencies reported by the loader are checked, e.g. external buffers and
+/// images of glTF are not, so `--rebuild-cache` is needed after changing them.
+pub fn read(key: &Key) -> io::Result<Option<LoadedModel>> {
+    let file = match File::open(&key.path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC || reader.read_u32::<LittleEndian>()? != VERSION {
+        return Ok(None);
+    }
+    if reader.read_u64::<LittleEndian>()? != key.options_hash {
+        return Ok(None);
+    }
+    let timestamp = read_timestamp(&mut reader)?;
+    let content_hash = reader.read_u64::<LittleEndian>()?;
+    if timestamp != Timestamp::of(&key.source)? && content_hash != hash_file(&key.source)? {
+        return Ok(None);
+    }
+    let mut dependencies = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let path = PathBuf::from(read_string(&mut reader)?);
+        let timestamp = read_timestamp(&mut reader)?;
+        if Timestamp::of(&path).ok() != Some(timestamp) {
+            return Ok(None);
+        }
+        dependencies.push(path);
+    }
+
+    let report = Report {
+        out_of_range_triangles: reader.read_u32::<LittleEndian>()?,
+        non_finite_triangles: reader.read_u32::<LittleEndian>()?,
+        degenerate_triangles: reader.read_u32::<LittleEndian>()?,
+        non_finite_attributes: reader.read_u32::<LittleEndian>()?,
+        duplicate_vertices: reader.read_u32::<LittleEndian>()?,
+        unused_vertices: reader.read_u32::<LittleEndian>()?,
+    };
+
+    let mut mesh = Mesh::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        mesh.models.push(Model {
+            indices_start: reader.read_u32::<LittleEndian>()?,
+            indices_end: reader.read_u32::<LittleEndian>()?,
+            material_idx: reader.read_i32::<LittleEndian>()?,
+        });
+    }
+    mesh.positions = read_f32_vec(&mut reader)?;
+    mesh.indices = read_u32_vec(&mut reader)?;
+    mesh.normals = read_f32_vec(&mut reader)?;
+    mesh.texcoords = read_f32_vec(&mut reader)?;
+    mesh.colors = read_f32_vec(&mut reader)?;
+
+    let mut materials = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        materials.push(tracers::ty::Material {
+            ambient: read_vec3(&mut reader)?,
+            diffuse: read_vec3(&mut reader)?,
+            specular: read_vec3(&mut reader)?,
+            shininess: reader.read_f32::<LittleEndian>()?,
+            dissolve: reader.read_f32::<LittleEndian>()?,
+            optical_density: reader.read_f32::<LittleEndian>()?,
+            ambient_texture_idx: reader.read_i32::<LittleEndian>()?,
+            diffuse_texture_idx: reader.read_i32::<LittleEndian>()?,
+            specular_texture_idx: reader.read_i32::<LittleEndian>()?,
+            normal_texture_idx: reader.read_i32::<LittleEndian>()?,
+            disolve_texture_idx: reader.read_i32::<LittleEndian>()?,
+            diffuse_source: reader.read_u32::<LittleEndian>()?,
+            _dummy0: [0; 4],
+            _dummy1: [0; 4],
+        });
+    }
+
+    let mut textures = Vec::new();
+    for _ in 0..reader.read_u32::<LittleEndian>()? {
+        let width = reader.read_u32::<LittleEndian>()?;
+        let height = reader.read_u32::<LittleEndian>()?;
+        let len = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|pixel_count| pixel_count.checked_mul(4))
+            .ok_or_else(|| invalid_data("texture size overflows"))?;
+        let pixels = read_bytes(&mut reader, len)?;
+        let texture = image::ImageBuffer::from_raw(width, height, pixels)
+            .ok_or_else(|| invalid_data("texture doesn't match its size"))?;
+        textures.push(texture);
+    }
+
+    Ok(Some(LoadedModel {
+        mesh,
+        materials,
+        textures,
+        dependencies,
+        report,
+    }))
+}
+
+pub fn write(key: &Key, model: &LoadedModel) -> io::Result<()> {
+    if let Some(dir) = key.path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // write into the temporary file first, so interrupted write
+    // doesn't leave a broken cache behind
+    let temp_path = key.path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_u32::<LittleEndian>(VERSION)?;
+        writer.write_u64::<LittleEndian>(key.options_hash)?;
+        write_timestamp(&mut writer, Timestamp::of(&key.source)?)?;
+        writer.write_u64::<LittleEndian>(hash_file(&key.source)?)?;
+        writer.write_u32::<LittleEndian>(model.dependencies.len() as u32)?;
+        for path in &model.dependencies {
+            write_string(&mut writer, &path.to_string_lossy())?;
+            write_timestamp(&mut writer, Timestamp::of(path)?)?;
+        }
+
+        let report = &model.report;
+        writer.write_u32::<LittleEndian>(report.out_of_range_triangles)?;
+        writer.write_u32::<LittleEndian>(report.non_finite_triangles)?;
+        writer.write_u32::<LittleEndian>(report.degenerate_triangles)?;
+        writer.write_u32::<LittleEndian>(report.non_finite_attributes)?;
+        writer.write_u32::<LittleEndian>(report.duplicate_vertices)?;
+        writer.write_u32::<LittleEndian>(report.unused_vertices)?;
+
+        let mesh = &model.mesh;
+        writer.write_u32::<LittleEndian>(mesh.models.len() as u32)?;
+        for m in &mesh.models {
+            writer.write_u32::<LittleEndian>(m.indices_start)?;
+            writer.write_u32::<LittleEndian>(m.indices_end)?;
+            writer.write_i32::<LittleEndian>(m.material_idx)?;
+        }
+        write_f32_slice(&mut writer, &mesh.positions)?;
+        write_u32_slice(&mut writer, &mesh.indices)?;
+        write_f32_slice(&mut writer, &mesh.normals)?;
+        write_f32_slice(&mut writer, &mesh.texcoords)?;
+        write_f32_slice(&mut writer, &mesh.colors)?;
+
+        writer.write_u32::<LittleEndian>(model.materials.len() as u32)?;
+        for m in &model.materials {
+            write_vec3(&mut writer, &m.ambient)?;
+            write_vec3(&mut writer, &m.diffuse)?;
+            write_vec3(&mut writer, &m.specular)?;
+            writer.write_f32::<LittleEndian>(m.shininess)?;
+            writer.write_f32::<LittleEndian>(m.dissolve)?;
+            writer.write_f32::<LittleEndian>(m.optical_density)?;
+            writer.write_i32::<LittleEndian>(m.ambient_texture_idx)?;
+            writer.write_i32::<LittleEndian>(m.diffuse_texture_idx)?;
+            writer.write_i32::<LittleEndian>(m.specular_texture_idx)?;
+            writer.write_i32::<LittleEndian>(m.normal_texture_idx)?;
+            writer.write_i32::<LittleEndian>(m.disolve_texture_idx)?;
+            writer.write_u32::<LittleEndian>(m.diffuse_source)?;
+        }
+
+        writer.write_u32::<LittleEndian>(model.textures.len() as u32)?;
+        for texture in &model.textures {
+            writer.write_u32::<LittleEndian>(texture.width())?;
+            writer.write_u32::<LittleEndian>(texture.height())?;
+            writer.write_all(texture)?;
+        }
+        writer.flush()?;
+    }
+    // rename doesn't replace existing files on every platform
+    let _ = fs::remove_file(&key.path);
+    fs::rename(&temp_path, &key.path)
+}
+
+fn hash_file(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = FnvHasher::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.write(&buffer[..count]);
+    }
+    Ok(hasher.finish())
+}
+
+fn read_timestamp<R: Read>(reader: &mut R) -> io::Result<Timestamp> {
+    Ok(Timestamp(
+        reader.read_u64::<LittleEndian>()?,
+        reader.read_u32::<LittleEndian>()?,
+    ))
+}
+
+fn write_timestamp<W: Write>(writer: &mut W, timestamp: Timestamp) -> io::Result<()> {
+    writer.write_u64::<LittleEndian>(timestamp.0)?;
+    writer.write_u32::<LittleEndian>(timestamp.1)
+}
+
+/// Lengths in the cache file aren't trusted, so the buffer grows while the data is read
+/// instead of being allocated up front, and a broken file can't make it larger than itself
+fn read_bytes<R: Read>(reader: &mut R, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(invalid_data("cache file is truncated"));
+    }
+    Ok(bytes)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    let bytes = read_bytes(reader, len as u64)?;
+    String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(string.len() as u32)?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_vec3<R: Read>(reader: &mut R) -> io::Result<[f32; 3]> {
+    Ok([
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+    ])
+}
+
+fn write_vec3<W: Write>(writer: &mut W, v: &[f32; 3]) -> io::Result<()> {
+    for &c in v {
+        writer.write_f32::<LittleEndian>(c)?;
+    }
+    Ok(())
+}
+
+fn read_f32_vec<R: Read>(reader: &mut R) -> io::Result<Vec<f32>> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    let bytes = read_bytes(reader, 4 * len as u64)?;
+    Ok(bytes.chunks(4).map(LittleEndian::read_f32).collect())
+}
+
+fn write_f32_slice<W: Write>(writer: &mut W, slice: &[f32]) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(slice.len() as u32)?;
+    for &v in slice {
+        writer.write_f32::<LittleEndian>(v)?;
+    }
+    Ok(())
+}
+
+fn read_u32_vec<R: Read>(reader: &mut R) -> io::Result<Vec<u32>> {
+    let len = reader.read_u32::<LittleEndian>()?;
+    let bytes = read_bytes(reader, 4 * len as u64)?;
+    Ok(bytes.chunks(4).map(LittleEndian::read_u32).collect())
+}
+
+fn write_u32_slice<W: Write>(writer: &mut W, slice: &[u32]) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(slice.len() as u32)?;
+    for &v in slice {
+        writer.write_u32::<LittleEndian>(v)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,367 @@
+extern crate cgmath;
+extern crate toml;
+
+use cgmath::SquareMatrix;
+
+use super::SceneError;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Declarative description of the scene, usually loaded from TOML file:
+///
+/// ```toml
+/// texture_paths = ["textures"]
+///
+/// [camera]
+/// position = [0.0, 1.0, 5.0]
+/// fov = [40.0, 40.0]
+///
+/// [render]
+/// resolution = [1280, 720]
+/// default_color = [0.8, 0.8, 0.8]
+/// anisotropy = 16.0
+///
+/// [cache]
+/// enabled = true
+///
+/// [[models]]
+/// path = "crate.obj"
+/// translate = [2.0, 0.0, 0.0]
+/// rotate = [0.0, 45.0, 0.0]
+/// normals = "smooth"
+/// crease_angle = 60.0
+/// repair = false
+/// up_axis = "z"
+/// unit_scale = 0.001
+/// recenter = true
+///
+/// [[lights]]
+/// type = "point"
+/// position = [0.0, 5.0, 0.0]
+/// intensity = 20.0
+///
+/// [[primitives]]
+/// type = "sphere"
+/// center = [0.0, 1.0, -2.0]
+/// radius = 1.0
+/// color = [0.8, 0.2, 0.2]
+///
+/// [[primitives]]
+/// type = "plane"
+/// normal = [0.0, 1.0, 0.0]
+///
+/// [environment]
+/// path = "sky.hdr"
+/// rotation = 90.0
+/// intensity = 1.0
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub render: RenderSettings,
+    pub models: Vec<ModelDescription>,
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+    /// Analytic shapes, which are intersected exactly instead of being tessellated
+    #[serde(default)]
+    pub primitives: Vec<PrimitiveDescription>,
+    /// Directories, where textures and material libraries are searched
+    /// if they are not found next to the model
+    #[serde(default)]
+    pub texture_paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub cache: CacheSettings,
+    /// Background of the scene, which also lights it
+    #[serde(default)]
+    pub environment: Option<EnvironmentDescription>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CameraDescription {
+    pub position: Option<[f32; 3]>,
+    pub fov: Option<[f32; 2]>,
+    /// Rotation around vertical axis in degrees
+    #[serde(default)]
+    pub yaw: f32,
+    /// Rotation around horizontal axis in degrees
+    #[serde(default)]
+    pub pitch: f32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RenderSettings {
+    pub resolution: Option<[u32; 2]>,
+    /// Diffuse color of the meshes without material
+    pub default_color: Option<[f32; 3]>,
+    /// Maximum anisotropy of the texture filtering, 1.0 turns it off
+    pub anisotropy: Option<f32>,
+}
+
+/// Loaded models are stored in the binary cache, so the next launch
+/// doesn't parse model files and decode textures again
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheSettings {
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Ignore existing cache and overwrite it
+    #[serde(default)]
+    pub rebuild: bool,
+}
+
+impl Default for CacheSettings {
+    fn default() -> CacheSettings {
+        CacheSettings {
+            enabled: true,
+            rebuild: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelDescription {
+    pub path: PathBuf,
+    #[serde(default = "zero")]
+    pub translate: [f32; 3],
+    /// Euler angles in degrees, applied in X, Y, Z order
+    #[serde(default = "zero")]
+    pub rotate: [f32; 3],
+    #[serde(default = "one")]
+    pub scale: [f32; 3],
+    /// How to generate normals if the model doesn't have them
+    #[serde(default)]
+    pub normals: NormalsMode,
+    /// Maximum angle in degrees between faces, which are smoothed together
+    #[serde(default = "default_crease_angle")]
+    pub crease_angle: f32,
+    /// Drop degenerate triangles, weld duplicate vertices and drop unused ones
+    #[serde(default)]
+    pub repair: bool,
+    /// Axis pointing up in the model, Z-up models are rotated to Y-up of the scene
+    #[serde(default)]
+    pub up_axis: UpAxis,
+    /// Converts units of the model to the scene ones, e.g. 0.001 for millimetres
+    #[serde(default = "unit")]
+    pub unit_scale: f32,
+    /// Move the center of the bounding box of the model to the origin
+    #[serde(default)]
+    pub recenter: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+impl Default for UpAxis {
+    fn default() -> UpAxis {
+        UpAxis::Y
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NormalsMode {
+    Flat,
+    Smooth,
+}
+
+impl Default for NormalsMode {
+    fn default() -> NormalsMode {
+        NormalsMode::Smooth
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum LightDescription {
+    Point {
+        position: [f32; 3],
+        #[serde(default = "one")]
+        color: [f32; 3],
+        #[serde(default = "unit")]
+        intensity: f32,
+    },
+    Directional {
+        direction: [f32; 3],
+        #[serde(default = "one")]
+        color: [f32; 3],
+        #[serde(default = "unit")]
+        intensity: f32,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrimitiveDescription {
+    #[serde(flatten)]
+    pub shape: Shape,
+    /// Diffuse color
+    #[serde(default = "default_color")]
+    pub color: [f32; 3],
+    #[serde(default = "zero")]
+    pub specular: [f32; 3],
+    #[serde(default)]
+    pub shininess: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Shape {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    /// Infinite plane through the point
+    Plane {
+        #[serde(default = "zero")]
+        point: [f32; 3],
+        normal: [f32; 3],
+    },
+    /// Axis aligned box
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+    Disk {
+        center: [f32; 3],
+        normal: [f32; 3],
+        radius: f32,
+    },
+}
+
+/// Equirectangular Radiance HDR or OpenEXR image
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnvironmentDescription {
+    pub path: PathBuf,
+    /// Rotation around the vertical axis in degrees
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "unit")]
+    pub intensity: f32,
+}
+
+impl EnvironmentDescription {
+    pub fn new(path: PathBuf) -> EnvironmentDescription {
+        EnvironmentDescription {
+            path,
+            rotation: 0.0,
+            intensity: unit(),
+        }
+    }
+}
+
+fn zero() -> [f32; 3] {
+    [0.0; 3]
+}
+
+fn one() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_color() -> [f32; 3] {
+    [0.8; 3]
+}
+
+fn unit() -> f32 {
+    1.0
+}
+
+fn enabled() -> bool {
+    true
+}
+
+fn default_crease_angle() -> f32 {
+    60.0
+}
+
+impl SceneDescription {
+    /// Scene with the single model and default settings
+    pub fn from_model(path: &Path) -> SceneDescription {
+        SceneDescription {
+            camera: CameraDescription::default(),
+            render: RenderSettings::default(),
+            models: vec![
+                ModelDescription {
+                    path: path.to_path_buf(),
+                    translate: zero(),
+                    rotate: zero(),
+                    scale: one(),
+                    normals: NormalsMode::default(),
+                    crease_angle: default_crease_angle(),
+                    repair: false,
+                    up_axis: UpAxis::default(),
+                    unit_scale: unit(),
+                    recenter: false,
+                },
+            ],
+            lights: Vec::new(),
+            primitives: Vec::new(),
+            texture_paths: Vec::new(),
+            cache: CacheSettings::default(),
+            environment: None,
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<SceneDescription, SceneError> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|error| SceneError::Io {
+                path: path.to_path_buf(),
+                error,
+            })?;
+        let mut description: SceneDescription =
+            toml::from_str(&content).map_err(|e| SceneError::Parse {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            })?;
+
+        // model, texture and environment paths are relative to the scene file
+        let scene_dir = path.parent().unwrap_or(Path::new(""));
+        for model in &mut description.models {
+            model.path = scene_dir.join(&model.path);
+        }
+        for texture_path in &mut description.texture_paths {
+            *texture_path = scene_dir.join(&texture_path);
+        }
+        if let Some(ref mut environment) = description.environment {
+            environment.path = scene_dir.join(&environment.path);
+        }
+        Ok(description)
+    }
+}
+
+impl ModelDescription {
+    /// Transform from the model space to the world space, the model is already converted
+    /// to the conventions of the scene when it is loaded
+    pub fn transform(&self) -> cgmath::Matrix4<f32> {
+        let translation = cgmath::Matrix4::from_translation(self.translate.into());
+        let rotation = cgmath::Matrix4::from_angle_z(cgmath::Deg(self.rotate[2]))
+            * cgmath::Matrix4::from_angle_y(cgmath::Deg(self.rotate[1]))
+            * cgmath::Matrix4::from_angle_x(cgmath::Deg(self.rotate[0]));
+        let scale =
+            cgmath::Matrix4::from_nonuniform_scale(self.scale[0], self.scale[1], self.scale[2]);
+        translation * rotation * scale
+    }
+
+    /// Brings the vertices of the loaded model to the conventions of the scene.
+    /// `center` is the center of their bounding box, which is used only to recenter them.
+    pub fn conversion(&self, center: [f32; 3]) -> cgmath::Matrix4<f32> {
+        let recenter = if self.recenter {
+            cgmath::Matrix4::from_translation(-cgmath::Vector3::from(center))
+        } else {
+            cgmath::Matrix4::identity()
+        };
+        // Z axis becomes Y, and Y becomes -Z, so the handedness is kept
+        let up = match self.up_axis {
+            UpAxis::Y => cgmath::Matrix4::identity(),
+            UpAxis::Z => cgmath::Matrix4::from_angle_x(cgmath::Deg(-90.0)),
+        };
+        cgmath::Matrix4::from_scale(self.unit_scale) * up * recenter
+    }
+}
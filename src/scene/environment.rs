@@ -0,0 +1,131 @@
+extern crate exr;
+extern crate image;
+
+use super::description::EnvironmentDescription;
+use super::SceneError;
+
+use std::cmp;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Equirectangular image in linear RGBA, with the full mip chain, so the shader
+/// can approximate light reflected by rough surfaces with the blurred levels
+pub struct EnvironmentMap {
+    /// Dimensions and pixels of every level, starting with the image itself
+    pub levels: Vec<(u32, u32, Vec<[f32; 4]>)>,
+    /// Rotation around the vertical axis in radians
+    pub rotation: f32,
+    pub intensity: f32,
+}
+
+impl EnvironmentMap {
+    pub fn load(description: &EnvironmentDescription) -> Result<EnvironmentMap, SceneError> {
+        let path = &description.path;
+        let (width, height, pixels) = match path.extension().and_then(|e| e.to_str()) {
+            Some("hdr") => load_hdr(path)?,
+            Some("exr") => load_exr(path)?,
+            _ => {
+                return Err(SceneError::Unsupported {
+                    path: path.clone(),
+                    feature: String::from("environment map format"),
+                })
+            }
+        };
+        let mut levels = vec![(width, height, pixels)];
+        loop {
+            let next = {
+                let &(width, height, ref pixels) = levels.last().unwrap();
+                if width == 1 && height == 1 {
+                    break;
+                }
+                downsample(width, height, pixels)
+            };
+            levels.push(next);
+        }
+        Ok(EnvironmentMap {
+            levels,
+            rotation: description.rotation.to_radians(),
+            intensity: description.intensity,
+        })
+    }
+}
+
+fn load_hdr(path: &Path) -> Result<(u32, u32, Vec<[f32; 4]>), SceneError> {
+    let file = File::open(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let to_scene_error = |error: image::ImageError| match error {
+        image::ImageError::IoError(error) => SceneError::Io {
+            path: path.to_path_buf(),
+            error,
+        },
+        error => SceneError::ImageDecode {
+            path: path.to_path_buf(),
+            error,
+        },
+    };
+    let decoder = image::hdr::HDRDecoder::new(BufReader::new(file)).map_err(&to_scene_error)?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr().map_err(&to_scene_error)?;
+    Ok((
+        metadata.width,
+        metadata.height,
+        pixels
+            .into_iter()
+            .map(|p| [p.data[0], p.data[1], p.data[2], 1.0])
+            .collect(),
+    ))
+}
+
+fn load_exr(path: &Path) -> Result<(u32, u32, Vec<[f32; 4]>), SceneError> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| {
+            (
+                resolution.width(),
+                vec![[0.0; 4]; resolution.width() * resolution.height()],
+            )
+        },
+        |&mut (width, ref mut pixels), position, (r, g, b, a): (f32, f32, f32, f32)| {
+            pixels[position.y() * width + position.x()] = [r, g, b, a];
+        },
+    ).map_err(|error| match error {
+        exr::error::Error::Io(error) => SceneError::Io {
+            path: path.to_path_buf(),
+            error,
+        },
+        error => SceneError::Parse {
+            path: path.to_path_buf(),
+            message: error.to_string(),
+        },
+    })?;
+    let size = image.layer_data.size;
+    let (_, pixels) = image.layer_data.channel_data.pixels;
+    Ok((size.width() as u32, size.height() as u32, pixels))
+}
+
+/// Box filter, the same as for the textures, but without sRGB conversion
+fn downsample(width: u32, height: u32, pixels: &[[f32; 4]]) -> (u32, u32, Vec<[f32; 4]>) {
+    let next_width = cmp::max(width / 2, 1);
+    let next_height = cmp::max(height / 2, 1);
+    let mut next = Vec::with_capacity((next_width * next_height) as usize);
+    for y in 0..next_height {
+        for x in 0..next_width {
+            let xs = [2 * x, cmp::min(2 * x + 1, width - 1)];
+            let ys = [2 * y, cmp::min(2 * y + 1, height - 1)];
+            let mut sum = [0.0; 4];
+            for &sx in &xs {
+                for &sy in &ys {
+                    let pixel = pixels[(sy * width + sx) as usize];
+                    for c in 0..4 {
+                        sum[c] += pixel[c] / 4.0;
+                    }
+                }
+            }
+            next.push(sum);
+        }
+    }
+    (next_width, next_height, next)
+}
@@ -0,0 +1,114 @@
+extern crate image;
+extern crate vulkano;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Everything that can go wrong while loading the scene and uploading it to GPU
+#[derive(Debug)]
+pub enum SceneError {
+    /// File exists, but can't be read
+    Io { path: PathBuf, error: io::Error },
+    /// File referenced by the model isn't found in any of the search paths
+    NotFound { name: String, tried: Vec<PathBuf> },
+    /// File is read, but its content is malformed
+    Parse { path: PathBuf, message: String },
+    ImageDecode {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    /// File is valid, but uses something the tracer can't render
+    Unsupported { path: PathBuf, feature: String },
+    GpuAlloc(String),
+}
+
+impl SceneError {
+    /// Loaders report malformed data as `InvalidData` I/O errors
+    pub fn from_io(path: PathBuf, error: io::Error) -> SceneError {
+        if error.kind() == io::ErrorKind::InvalidData {
+            SceneError::Parse {
+                path,
+                message: error.to_string(),
+            }
+        } else {
+            SceneError::Io { path, error }
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SceneError::Io {
+                ref path,
+                ref error,
+            } => write!(f, "can't read {}: {}", path.display(), error),
+            SceneError::NotFound {
+                ref name,
+                ref tried,
+            } => {
+                write!(f, "can't find {}, tried:", name)?;
+                for path in tried {
+                    write!(f, "\n    {}", path.display())?;
+                }
+                Ok(())
+            }
+            SceneError::Parse {
+                ref path,
+                ref message,
+            } => write!(f, "can't parse {}: {}", path.display(), message),
+            SceneError::ImageDecode {
+                ref path,
+                ref error,
+            } => write!(f, "can't decode image {}: {}", path.display(), error),
+            SceneError::Unsupported {
+                ref path,
+                ref feature,
+            } => write!(f, "{} uses unsupported {}", path.display(), feature),
+            SceneError::GpuAlloc(ref message) => {
+                write!(f, "can't allocate GPU memory: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn description(&self) -> &str {
+        match *self {
+            SceneError::Io { .. } => "I/O error",
+            SceneError::NotFound { .. } => "file not found",
+            SceneError::Parse { .. } => "parse error",
+            SceneError::ImageDecode { .. } => "image decode error",
+            SceneError::Unsupported { .. } => "unsupported feature",
+            SceneError::GpuAlloc(_) => "GPU allocation error",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            SceneError::Io { ref error, .. } => Some(error),
+            SceneError::ImageDecode { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<vulkano::memory::DeviceMemoryAllocError> for SceneError {
+    fn from(error: vulkano::memory::DeviceMemoryAllocError) -> SceneError {
+        SceneError::GpuAlloc(error.to_string())
+    }
+}
+
+impl From<vulkano::OomError> for SceneError {
+    fn from(error: vulkano::OomError) -> SceneError {
+        SceneError::GpuAlloc(error.to_string())
+    }
+}
+
+impl From<vulkano::image::ImageCreationError> for SceneError {
+    fn from(error: vulkano::image::ImageCreationError) -> SceneError {
+        SceneError::GpuAlloc(error.to_string())
+    }
+}
@@ -0,0 +1,180 @@
+extern crate cgmath;
+
+use cgmath::InnerSpace;
+
+use super::description::NormalsMode;
+use super::Mesh;
+
+use std::cmp;
+use std::collections::HashMap;
+
+/// Generates normals and texture coordinates if the mesh doesn't have them
+/// for every vertex, so the shader never reads outside of the buffers.
+/// Incomplete colors are dropped, the mesh is white then.
+pub fn fill_missing_attributes(mesh: &mut Mesh, mode: NormalsMode, crease_angle: f32) {
+    let vertex_count = mesh.positions.len() / 3;
+    if mesh.texcoords.len() != 2 * vertex_count {
+        mesh.texcoords.clear();
+    }
+    if mesh.colors.len() != 3 * vertex_count {
+        mesh.colors.clear();
+    }
+    if mesh.normals.len() != 3 * vertex_count {
+        match mode {
+            NormalsMode::Flat => flat_normals(mesh),
+            NormalsMode::Smooth => smooth_normals(mesh, crease_angle),
+        }
+    }
+    if mesh.texcoords.is_empty() {
+        planar_texcoords(mesh);
+    }
+}
+
+/// Every triangle gets its own vertices with the face normal
+fn flat_normals(mesh: &mut Mesh) {
+    let has_texcoords = !mesh.texcoords.is_empty();
+    let has_colors = !mesh.colors.is_empty();
+    let mut positions = Vec::with_capacity(3 * mesh.indices.len());
+    let mut normals = Vec::with_capacity(3 * mesh.indices.len());
+    let mut texcoords = Vec::new();
+    let mut colors = Vec::new();
+    for triangle in mesh.indices.chunks(3) {
+        let normal = normalize_or_zero(face_normal(&mesh.positions, triangle));
+        for &i in triangle {
+            let i = i as usize;
+            positions.extend_from_slice(&mesh.positions[3 * i..3 * i + 3]);
+            normals.extend_from_slice(&[normal.x, normal.y, normal.z]);
+            if has_texcoords {
+                texcoords.extend_from_slice(&mesh.texcoords[2 * i..2 * i + 2]);
+            }
+            if has_colors {
+                colors.extend_from_slice(&mesh.colors[3 * i..3 * i + 3]);
+            }
+        }
+    }
+    mesh.indices = (0..mesh.indices.len() as u32).collect();
+    mesh.positions = positions;
+    mesh.normals = normals;
+    mesh.texcoords = texcoords;
+    mesh.colors = colors;
+}
+
+/// Area weighted average of the normals of adjacent faces. Faces which normals
+/// differ by more than `crease_angle` degrees are not averaged, so their common
+/// vertices are split to keep the edge sharp.
+fn smooth_normals(mesh: &mut Mesh, crease_angle: f32) {
+    let cos_crease = crease_angle.to_radians().cos();
+    let face_normals: Vec<_> = mesh.indices
+        .chunks(3)
+        .map(|triangle| face_normal(&mesh.positions, triangle))
+        .collect();
+
+    // vertices with equal positions are treated as one, because loaders
+    // split vertices on texture seams, which must not be visible in shading
+    let mut position_ids = HashMap::new();
+    let welded: Vec<usize> = mesh.positions
+        .chunks(3)
+        .map(|p| {
+            let key = (p[0].to_bits(), p[1].to_bits(), p[2].to_bits());
+            let next_id = position_ids.len();
+            *position_ids.entry(key).or_insert(next_id)
+        })
+        .collect();
+    let mut adjacent_faces = vec![Vec::new(); position_ids.len()];
+    for (face, triangle) in mesh.indices.chunks(3).enumerate() {
+        for &i in triangle {
+            adjacent_faces[welded[i as usize]].push(face);
+        }
+    }
+
+    let has_texcoords = !mesh.texcoords.is_empty();
+    let has_colors = !mesh.colors.is_empty();
+    let mut positions = Vec::with_capacity(mesh.positions.len());
+    let mut normals = Vec::with_capacity(mesh.positions.len());
+    let mut texcoords = Vec::new();
+    let mut colors = Vec::new();
+    let mut new_indices = HashMap::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for (face, triangle) in mesh.indices.chunks(3).enumerate() {
+        let face_direction = normalize_or_zero(face_normals[face]);
+        for &i in triangle {
+            let i = i as usize;
+            let normal = normalize_or_zero(
+                adjacent_faces[welded[i]]
+                    .iter()
+                    .map(|&other| face_normals[other])
+                    .filter(|&n| face_direction.dot(normalize_or_zero(n)) >= cos_crease)
+                    .fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, n| sum + n),
+            );
+            let key = (i, normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits());
+            let next_index = positions.len() as u32 / 3;
+            let index = *new_indices.entry(key).or_insert_with(|| {
+                positions.extend_from_slice(&mesh.positions[3 * i..3 * i + 3]);
+                normals.extend_from_slice(&[normal.x, normal.y, normal.z]);
+                if has_texcoords {
+                    texcoords.extend_from_slice(&mesh.texcoords[2 * i..2 * i + 2]);
+                }
+                if has_colors {
+                    colors.extend_from_slice(&mesh.colors[3 * i..3 * i + 3]);
+                }
+                next_index
+            });
+            indices.push(index);
+        }
+    }
+    mesh.indices = indices;
+    mesh.positions = positions;
+    mesh.normals = normals;
+    mesh.texcoords = texcoords;
+    mesh.colors = colors;
+}
+
+/// Projects vertices onto the plane of two largest dimensions of the bounding box,
+/// so textures are at least stretched over the model instead of a single texel
+fn planar_texcoords(mesh: &mut Mesh) {
+    let mut min = [::std::f32::INFINITY; 3];
+    let mut max = [::std::f32::NEG_INFINITY; 3];
+    for p in mesh.positions.chunks(3) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    let extent = [max[0] - min[0], max[1] - min[1], max[2] - min[2]];
+    let mut axes = [0, 1, 2];
+    axes.sort_by(|&a, &b| {
+        extent[b]
+            .partial_cmp(&extent[a])
+            .unwrap_or(cmp::Ordering::Equal)
+    });
+    let (s_axis, t_axis) = (axes[0], axes[1]);
+    let normalize = |value: f32, axis: usize| {
+        if extent[axis] > 0.0 {
+            (value - min[axis]) / extent[axis]
+        } else {
+            0.0
+        }
+    };
+    mesh.texcoords = mesh.positions
+        .chunks(3)
+        .flat_map(|p| vec![normalize(p[s_axis], s_axis), normalize(p[t_axis], t_axis)])
+        .collect();
+}
+
+/// Not normalized, so its length is proportional to the area of the face
+fn face_normal(positions: &[f32], triangle: &[u32]) -> cgmath::Vector3<f32> {
+    let vertex = |i: u32| {
+        let i = i as usize;
+        cgmath::Vector3::new(positions[3 * i], positions[3 * i + 1], positions[3 * i + 2])
+    };
+    let v0 = vertex(triangle[0]);
+    (vertex(triangle[1]) - v0).cross(vertex(triangle[2]) - v0)
+}
+
+fn normalize_or_zero(v: cgmath::Vector3<f32>) -> cgmath::Vector3<f32> {
+    if v.magnitude2() > 0.0 {
+        v.normalize()
+    } else {
+        v
+    }
+}
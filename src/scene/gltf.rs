@@ -0,0 +1,247 @@
+extern crate cgmath;
+extern crate image;
+
+use cgmath::SquareMatrix;
+use gltf;
+
+use super::description::ModelDescription;
+use super::validate::Report;
+use super::{
+    default_material, diffuse_source, prepare_part, LoadedModel, Mesh, Model, DIFFUSE_VERTEX,
+};
+use tracers;
+
+use std::collections::HashMap;
+
+pub fn load(description: &ModelDescription) -> Result<LoadedModel, gltf::Error> {
+    let (document, buffers, images) = gltf::import(&description.path)?;
+
+    let mut mesh = Mesh::new();
+    let mut report = Report::default();
+
+    let (mut materials, textures) = load_materials(&document, &images);
+    // primitives without material use the default one from the specification,
+    // which is appended after all materials of the document
+    let default_material_idx = materials.len() as i32;
+    materials.push(default_material([1.0; 3]));
+    // primitives with vertex colors use copies of the materials, which multiply
+    // the base color by them, unless it is textured
+    let vertex_color_offset = materials.len() as i32;
+    let vertex_color_materials: Vec<_> = materials
+        .iter()
+        .map(|material| tracers::ty::Material {
+            diffuse_source: if material.diffuse_texture_idx == -1 {
+                DIFFUSE_VERTEX
+            } else {
+                material.diffuse_source
+            },
+            ..material.clone()
+        })
+        .collect();
+    materials.extend(vertex_color_materials);
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next());
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            load_node(
+                &node,
+                cgmath::Matrix4::identity(),
+                &buffers,
+                default_material_idx,
+                vertex_color_offset,
+                description,
+                &mut mesh,
+                &mut report,
+            );
+        }
+    }
+
+    Ok(LoadedModel {
+        mesh,
+        materials,
+        textures,
+        dependencies: Vec::new(),
+        report,
+    })
+}
+
+fn load_node(
+    node: &gltf::Node,
+    parent_transform: cgmath::Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    default_material_idx: i32,
+    vertex_color_offset: i32,
+    description: &ModelDescription,
+    mesh: &mut Mesh,
+    report: &mut Report,
+) {
+    let transform = parent_transform * cgmath::Matrix4::from(node.transform().matrix());
+    if let Some(node_mesh) = node.mesh() {
+        for primitive in node_mesh.primitives() {
+            load_primitive(
+                &primitive,
+                &transform,
+                buffers,
+                default_material_idx,
+                vertex_color_offset,
+                description,
+                mesh,
+                report,
+            );
+        }
+    }
+    for child in node.children() {
+        load_node(
+            &child,
+            transform,
+            buffers,
+            default_material_idx,
+            vertex_color_offset,
+            description,
+            mesh,
+            report,
+        );
+    }
+}
+
+fn load_primitive(
+    primitive: &gltf::Primitive,
+    transform: &cgmath::Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    default_material_idx: i32,
+    vertex_color_offset: i32,
+    description: &ModelDescription,
+    mesh: &mut Mesh,
+    report: &mut Report,
+) {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        println!(
+            "skipping glTF primitive with unsupported mode {:?}",
+            primitive.mode()
+        );
+        return;
+    }
+
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let positions: Vec<f32> = match reader.read_positions() {
+        Some(positions) => positions.flat_map(|p| p.to_vec()).collect(),
+        None => return,
+    };
+    let normals: Vec<f32> = match reader.read_normals() {
+        Some(normals) => normals.flat_map(|n| n.to_vec()).collect(),
+        None => Vec::new(),
+    };
+    let texcoords: Vec<f32> = match reader.read_tex_coords(0) {
+        Some(texcoords) => texcoords.into_f32().flat_map(|st| st.to_vec()).collect(),
+        None => Vec::new(),
+    };
+    let indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32 / 3).collect(),
+    };
+    let colors: Vec<f32> = match reader.read_colors(0) {
+        Some(colors) => colors.into_rgb_f32().flat_map(|c| c.to_vec()).collect(),
+        None => Vec::new(),
+    };
+
+    let material_idx = primitive
+        .material()
+        .index()
+        .map(|idx| idx as i32)
+        .unwrap_or(default_material_idx);
+    let material_idx = if colors.is_empty() {
+        material_idx
+    } else {
+        material_idx + vertex_color_offset
+    };
+    let mut part = Mesh {
+        models: vec![
+            Model {
+                indices_start: 0,
+                indices_end: indices.len() as u32 / 3,
+                material_idx: material_idx,
+            },
+        ],
+        positions,
+        indices,
+        normals,
+        texcoords,
+        colors,
+    };
+    prepare_part(&mut part, description, report);
+    mesh.append(part, transform, 0);
+}
+
+fn load_materials(
+    document: &gltf::Document,
+    images: &[gltf::image::Data],
+) -> (Vec<tracers::ty::Material>, Vec<image::RgbaImage>) {
+    let mut materials = Vec::new();
+    let mut textures = Vec::new();
+    // several materials can share the same image, so upload it only once
+    let mut texture_indices = HashMap::new();
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let base_color = pbr.base_color_factor();
+        let texture_idx = match pbr.base_color_texture() {
+            Some(info) => {
+                let image_idx = info.texture().source().index();
+                if !texture_indices.contains_key(&image_idx) {
+                    if let Some(texture) = to_rgba_image(&images[image_idx]) {
+                        texture_indices.insert(image_idx, textures.len() as i32);
+                        textures.push(texture);
+                    } else {
+                        println!(
+                            "skipping glTF image {} with unsupported format {:?}",
+                            image_idx,
+                            images[image_idx].format
+                        );
+                        texture_indices.insert(image_idx, -1);
+                    }
+                }
+                texture_indices[&image_idx]
+            }
+            None => -1,
+        };
+        materials.push(tracers::ty::Material {
+            ambient: [0.0; 3],
+            diffuse: [base_color[0], base_color[1], base_color[2]],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            dissolve: base_color[3],
+            optical_density: 1.0,
+            ambient_texture_idx: -1,
+            diffuse_texture_idx: texture_idx,
+            specular_texture_idx: -1,
+            normal_texture_idx: -1,
+            disolve_texture_idx: -1,
+            diffuse_source: diffuse_source(texture_idx),
+            _dummy0: [0; 4],
+            _dummy1: [0; 4],
+        });
+    }
+    (materials, textures)
+}
+
+fn to_rgba_image(data: &gltf::image::Data) -> Option<image::RgbaImage> {
+    use gltf::image::Format;
+    let pixels: Vec<u8> = match data.format {
+        Format::R8G8B8A8 => data.pixels.clone(),
+        Format::R8G8B8 => data.pixels
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8 => data.pixels
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        Format::R8 => data.pixels
+            .iter()
+            .flat_map(|&p| vec![p, p, p, 255])
+            .collect(),
+        _ => return None,
+    };
+    image::ImageBuffer::from_raw(data.width, data.height, pixels)
+}
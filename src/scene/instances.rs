@@ -0,0 +1,153 @@
+extern crate cgmath;
+
+use cgmath::SquareMatrix;
+
+use tracers;
+
+use std::cmp;
+
+/// Leaves with this many instances or less are not split further
+const MAX_LEAF_SIZE: usize = 2;
+/// Size of the traversal stack in `intersect_scene`, must match `tracer.comp.tera`.
+/// The stack holds one node more than the depth of the tree.
+const STACK_SIZE: usize = 32;
+
+/// Axis aligned bounding box of the instance in world space
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Bounds {
+    fn empty() -> Bounds {
+        Bounds {
+            min: [::std::f32::INFINITY; 3],
+            max: [::std::f32::NEG_INFINITY; 3],
+        }
+    }
+
+    fn of_instance(instance: &tracers::ty::Instance) -> Bounds {
+        let object_to_world = cgmath::Matrix4::from(instance.world_to_object)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        let corners = [instance.bbox_min, instance.bbox_max];
+        let mut bounds = Bounds::empty();
+        for i in 0..8 {
+            let corner = cgmath::Vector4::new(
+                corners[i & 1][0],
+                corners[(i >> 1) & 1][1],
+                corners[(i >> 2) & 1][2],
+                1.0,
+            );
+            let p = object_to_world * corner;
+            bounds.add_point([p.x, p.y, p.z]);
+        }
+        bounds
+    }
+
+    fn add_point(&mut self, p: [f32; 3]) {
+        for axis in 0..3 {
+            self.min[axis] = self.min[axis].min(p[axis]);
+            self.max[axis] = self.max[axis].max(p[axis]);
+        }
+    }
+
+    fn add(&mut self, other: &Bounds) {
+        self.add_point(other.min);
+        self.add_point(other.max);
+    }
+
+    fn center(&self, axis: usize) -> f32 {
+        0.5 * (self.min[axis] + self.max[axis])
+    }
+}
+
+/// Builds the top level hierarchy over instance bounds. Instances are reordered,
+/// so every leaf references the continuous range of them. Children of the inner
+/// node are stored next to each other and the first one is referenced by the node.
+pub fn build_tree(
+    instances: Vec<tracers::ty::Instance>,
+) -> (Vec<tracers::ty::Instance>, Vec<tracers::ty::InstanceNode>) {
+    let mut items: Vec<_> = instances
+        .into_iter()
+        .map(|instance| (Bounds::of_instance(&instance), instance))
+        .collect();
+    let mut nodes = vec![empty_node()];
+    if !items.is_empty() {
+        let len = items.len();
+        let depth = split(&mut items, 0, len, 0, &mut nodes);
+        // median splits make the tree balanced, so only billions of instances get here
+        assert!(
+            depth < STACK_SIZE,
+            "instance tree of depth {} doesn't fit the traversal stack",
+            depth
+        );
+    }
+    let instances = items.into_iter().map(|(_, instance)| instance).collect();
+    (instances, nodes)
+}
+
+/// Returns the depth of the subtree
+fn split(
+    items: &mut [(Bounds, tracers::ty::Instance)],
+    start: usize,
+    end: usize,
+    node_idx: usize,
+    nodes: &mut Vec<tracers::ty::InstanceNode>,
+) -> usize {
+    let mut bounds = Bounds::empty();
+    let mut centers = Bounds::empty();
+    for &(ref b, _) in &items[start..end] {
+        bounds.add(b);
+        centers.add_point([b.center(0), b.center(1), b.center(2)]);
+    }
+    nodes[node_idx].min = bounds.min;
+    nodes[node_idx].max = bounds.max;
+
+    if end - start <= MAX_LEAF_SIZE {
+        nodes[node_idx].first = start as u32;
+        nodes[node_idx].count = (end - start) as u32;
+        return 0;
+    }
+
+    // median split along the longest axis of the centers
+    let extent = [
+        centers.max[0] - centers.min[0],
+        centers.max[1] - centers.min[1],
+        centers.max[2] - centers.min[2],
+    ];
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            extent[a]
+                .partial_cmp(&extent[b])
+                .unwrap_or(cmp::Ordering::Equal)
+        })
+        .unwrap();
+    items[start..end].sort_by(|a, b| {
+        a.0
+            .center(axis)
+            .partial_cmp(&b.0.center(axis))
+            .unwrap_or(cmp::Ordering::Equal)
+    });
+    let middle = (start + end) / 2;
+
+    let left = nodes.len();
+    nodes.push(empty_node());
+    nodes.push(empty_node());
+    nodes[node_idx].first = left as u32;
+    nodes[node_idx].count = 0;
+    let left_depth = split(items, start, middle, left, nodes);
+    let right_depth = split(items, middle, end, left + 1, nodes);
+    1 + cmp::max(left_depth, right_depth)
+}
+
+fn empty_node() -> tracers::ty::InstanceNode {
+    // inverted bounds are never hit by a ray
+    tracers::ty::InstanceNode {
+        min: [1.0; 3],
+        first: 0,
+        max: [-1.0; 3],
+        count: 0,
+    }
+}
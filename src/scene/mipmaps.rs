@@ -0,0 +1,72 @@
+extern crate image;
+
+use std::cmp;
+
+/// Full mip chain of the image down to 1x1, starting with the image itself.
+/// Every level is the box filtered previous one, colors are averaged in linear
+/// space, because color pages are sampled as sRGB. Pixels of the `linear` images
+/// are averaged as they are.
+pub fn generate(image: image::RgbaImage, linear: bool) -> Vec<image::RgbaImage> {
+    let mut levels = vec![image];
+    loop {
+        let next = {
+            let previous = levels.last().unwrap();
+            let (width, height) = previous.dimensions();
+            if width == 1 && height == 1 {
+                break;
+            }
+            downsample(previous, linear)
+        };
+        levels.push(next);
+    }
+    levels
+}
+
+fn downsample(image: &image::RgbaImage, linear: bool) -> image::RgbaImage {
+    // color channels of sRGB images are averaged in linear space
+    let (decode, encode): (fn(u8) -> f32, fn(f32) -> u8) = if linear {
+        (to_unit, from_unit)
+    } else {
+        (to_linear, to_srgb)
+    };
+    let (width, height) = image.dimensions();
+    let next_width = cmp::max(width / 2, 1);
+    let next_height = cmp::max(height / 2, 1);
+    image::RgbaImage::from_fn(next_width, next_height, |x, y| {
+        // odd sizes drop the last row or column, which is barely visible
+        let xs = [2 * x, cmp::min(2 * x + 1, width - 1)];
+        let ys = [2 * y, cmp::min(2 * y + 1, height - 1)];
+        let mut sum = [0.0; 4];
+        for &sx in &xs {
+            for &sy in &ys {
+                let pixel = image.get_pixel(sx, sy);
+                for c in 0..3 {
+                    sum[c] += decode(pixel[c]);
+                }
+                sum[3] += to_unit(pixel[3]);
+            }
+        }
+        image::Rgba([
+            encode(sum[0] / 4.0),
+            encode(sum[1] / 4.0),
+            encode(sum[2] / 4.0),
+            from_unit(sum[3] / 4.0),
+        ])
+    })
+}
+
+fn to_linear(value: u8) -> f32 {
+    (value as f32 / 255.0).powf(2.2)
+}
+
+fn to_srgb(value: f32) -> u8 {
+    (value.powf(1.0 / 2.2) * 255.0).round() as u8
+}
+
+fn to_unit(value: u8) -> f32 {
+    value as f32 / 255.0
+}
+
+fn from_unit(value: f32) -> u8 {
+    (value * 255.0).round() as u8
+}
@@ -0,0 +1,1571 @@
+extern crate cgmath;
+extern crate image;
+extern crate tobj;
+extern crate vulkano;
+
+mod atlas;
+mod cache;
+mod description;
+mod environment;
+mod error;
+mod generate;
+mod gltf;
+mod instances;
+mod mipmaps;
+mod ply;
+mod primitives;
+mod stl;
+mod validate;
+mod watcher;
+
+pub use self::description::{EnvironmentDescription, LightDescription, ModelDescription,
+                            NormalsMode, SceneDescription, UpAxis};
+pub use self::error::SceneError;
+pub use self::watcher::SceneWatcher;
+
+use self::atlas::Atlas;
+
+use cgmath::{InnerSpace, Matrix, SquareMatrix};
+use gl_types::{FromArr2, FromArr3, UVec3, Vec2, Vec3};
+
+use vulkano::sync::GpuFuture;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tracers;
+
+pub struct ModelBuffers {
+    pub positions: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub indices: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub normals: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub texcoords: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Vertex colors, a single white color if no model has them
+    pub colors: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub materials: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Atlas pages, sRGB for the colors and UNORM for the linear data
+    pub textures: Vec<Arc<vulkano::image::ImageViewAccess + Send + Sync>>,
+    pub sampler: Arc<vulkano::sampler::Sampler>,
+    pub environment_map: Arc<vulkano::image::ImmutableImage<vulkano::format::R32G32B32A32Sfloat>>,
+    pub environment_sampler: Arc<vulkano::sampler::Sampler>,
+    pub environment: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub texture_rects: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub lights: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub instances: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub instance_nodes: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub triangle_materials: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    pub primitives: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Minimum and maximum of every primitive inserted into the grid
+    pub primitive_bounds: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+    /// Bounds of all primitives inserted into the grid, if there are any
+    pub primitives_bbox: Option<([f32; 3], [f32; 3])>,
+    pub model_count: usize,
+    pub triangle_count: usize,
+    /// Number of primitives inserted into the grid, unbounded planes are not counted
+    pub primitive_count: usize,
+    /// Object space bounds of every triangle followed by every bounded primitive,
+    /// indexed the same way as the references of the acceleration structures
+    pub object_bounds: Vec<([f32; 3], [f32; 3])>,
+}
+
+/// Whole scene loaded into memory, but not yet uploaded to GPU.
+/// Doesn't depend on the device, so it can be loaded in the background.
+pub struct LoadedScene {
+    /// Geometry of every distinct model, placed side by side in the object space
+    mesh: Mesh,
+    materials: Vec<tracers::ty::Material>,
+    textures: Vec<image::RgbaImage>,
+    lights: Vec<tracers::ty::Light>,
+    instances: Vec<tracers::ty::Instance>,
+    instance_nodes: Vec<tracers::ty::InstanceNode>,
+    primitives: primitives::Primitives,
+    max_anisotropy: f32,
+    environment: Option<environment::EnvironmentMap>,
+    /// Model files and everything they reference
+    pub files: Vec<PathBuf>,
+}
+
+/// Distinct model, which may be instanced several times
+struct PlacedMesh {
+    /// Translation from the model space to the shared object space
+    offset: cgmath::Vector3<f32>,
+    bbox_min: [f32; 3],
+    bbox_max: [f32; 3],
+    triangles_start: u32,
+    triangles_end: u32,
+}
+
+pub fn load_scene(description: &SceneDescription) -> Result<LoadedScene, SceneError> {
+    let mut mesh = Mesh::new();
+    let mut materials = Vec::new();
+    let mut textures = Vec::new();
+    let mut files = Vec::new();
+    let mut instances = Vec::new();
+    // models used several times are loaded once and instanced
+    let mut placed_meshes = HashMap::new();
+    // distinct models are placed one after another along X axis, so the grid
+    // built over all of them doesn't mix their triangles in the same cells
+    let mut layout_cursor = 0.0;
+    for model in &description.models {
+        // the same options as in cache::Key, the repaired or converted model differs
+        // from the original
+        let key = (
+            model.path.clone(),
+            model.normals,
+            model.crease_angle.to_bits(),
+            model.repair,
+            model.up_axis,
+            model.unit_scale.to_bits(),
+            model.recenter,
+        );
+        if !placed_meshes.contains_key(&key) {
+            let loaded = load_cached_model(model, description)?;
+            if !loaded.report.is_clean() {
+                let hint = if model.repair {
+                    ", repaired"
+                } else if loaded.report.is_repairable() {
+                    ", use --repair to fix"
+                } else {
+                    ""
+                };
+                println!("warning: {}: {}{}", model.path.display(), loaded.report, hint);
+            }
+            let (min, max) = bounds(&loaded.mesh.positions);
+            let offset = cgmath::Vector3::new(layout_cursor - min[0], 0.0, 0.0);
+            layout_cursor += max[0] - min[0];
+            let triangles_start = mesh.indices.len() as u32 / 3;
+            mesh.append(
+                loaded.mesh,
+                &cgmath::Matrix4::from_translation(offset),
+                materials.len() as i32,
+            );
+            let texture_offset = textures.len() as i32;
+            materials.extend(
+                loaded
+                    .materials
+                    .into_iter()
+                    .map(|m| offset_texture_indices(m, texture_offset)),
+            );
+            textures.extend(loaded.textures);
+            files.push(model.path.clone());
+            files.extend(loaded.dependencies);
+            placed_meshes.insert(
+                key.clone(),
+                PlacedMesh {
+                    offset,
+                    bbox_min: [min[0] + offset.x, min[1], min[2]],
+                    bbox_max: [max[0] + offset.x, max[1], max[2]],
+                    triangles_start,
+                    triangles_end: mesh.indices.len() as u32 / 3,
+                },
+            );
+        }
+
+        let placed = &placed_meshes[&key];
+        let world_to_model = match model.transform().invert() {
+            Some(inverse) => inverse,
+            None => {
+                println!("warning: skipping {:?} with degenerate transform", model.path);
+                continue;
+            }
+        };
+        let world_to_object = cgmath::Matrix4::from_translation(placed.offset) * world_to_model;
+        instances.push(tracers::ty::Instance {
+            world_to_object: world_to_object.into(),
+            bbox_min: placed.bbox_min,
+            triangles_start: placed.triangles_start,
+            bbox_max: placed.bbox_max,
+            triangles_end: placed.triangles_end,
+        });
+    }
+
+    let default_material_idx = materials.len() as i32;
+    let mut fallback_count = 0;
+    for model in &mut mesh.models {
+        if model.material_idx == NO_MATERIAL {
+            model.material_idx = default_material_idx;
+            fallback_count += 1;
+        }
+    }
+    if fallback_count > 0 {
+        println!(
+            "warning: {} meshes have no material, the default one is used",
+            fallback_count
+        );
+    }
+    materials.push(default_material(
+        description.render.default_color.unwrap_or(DEFAULT_DIFFUSE),
+    ));
+
+    // primitives are numbered after the triangles in the grid and placed after the models
+    let primitives =
+        primitives::Primitives::new(&description.primitives, layout_cursor, &mut materials);
+    if let Some((bbox_min, bbox_max)) = primitives.bbox() {
+        let triangle_count = mesh.indices.len() as u32 / 3;
+        instances.push(tracers::ty::Instance {
+            world_to_object: cgmath::Matrix4::from_translation(primitives.offset).into(),
+            bbox_min,
+            triangles_start: triangle_count,
+            bbox_max,
+            triangles_end: triangle_count + primitives.bounds.len() as u32,
+        });
+    }
+
+    let environment = match description.environment {
+        Some(ref environment) => {
+            files.push(environment.path.clone());
+            Some(environment::EnvironmentMap::load(environment)?)
+        }
+        None => None,
+    };
+
+    let (instances, instance_nodes) = instances::build_tree(instances);
+    Ok(LoadedScene {
+        mesh,
+        materials,
+        textures,
+        lights: load_lights(&description.lights),
+        instances,
+        instance_nodes,
+        primitives,
+        max_anisotropy: description.render.anisotropy.unwrap_or(1.0),
+        environment,
+        files,
+    })
+}
+
+/// Model loaded into memory, but not yet uploaded to GPU
+pub struct LoadedModel {
+    pub mesh: Mesh,
+    pub materials: Vec<tracers::ty::Material>,
+    pub textures: Vec<image::RgbaImage>,
+    /// Files besides the model itself, which the model was loaded from
+    pub dependencies: Vec<PathBuf>,
+    /// Problems found while loading the model
+    pub report: validate::Report,
+}
+
+impl LoadedModel {
+    /// Formats without groups are validated and completed as a whole
+    fn from_single_part(
+        (mut mesh, materials, textures): (Mesh, Vec<tracers::ty::Material>, Vec<image::RgbaImage>),
+        description: &ModelDescription,
+    ) -> LoadedModel {
+        let mut report = validate::Report::default();
+        prepare_part(&mut mesh, description, &mut report);
+        LoadedModel {
+            mesh,
+            materials,
+            textures,
+            dependencies: Vec::new(),
+            report,
+        }
+    }
+}
+
+/// Triangle range of the OBJ group or glTF primitive, the materials of the triangles
+/// are uploaded per triangle
+#[derive(Debug, Clone, Copy)]
+pub struct Model {
+    pub indices_start: u32,
+    pub indices_end: u32,
+    pub material_idx: i32,
+}
+
+/// Flattened scene geometry, laid out the same way as the GPU buffers
+pub struct Mesh {
+    pub models: Vec<Model>,
+    pub positions: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub normals: Vec<f32>,
+    pub texcoords: Vec<f32>,
+    /// RGB color of every vertex, or empty if the mesh doesn't have them
+    pub colors: Vec<f32>,
+}
+
+impl Mesh {
+    fn new() -> Mesh {
+        Mesh {
+            models: Vec::new(),
+            positions: Vec::new(),
+            indices: Vec::new(),
+            normals: Vec::new(),
+            texcoords: Vec::new(),
+            colors: Vec::new(),
+        }
+    }
+
+    /// Appends geometry of another mesh transformed to the world space
+    fn append(&mut self, mut other: Mesh, transform: &cgmath::Matrix4<f32>, material_offset: i32) {
+        let first_triangle = self.indices.len() as u32 / 3;
+        let first_vertex = self.positions.len() as u32 / 3;
+        let vertex_count = first_vertex as usize + other.positions.len() / 3;
+        if !self.colors.is_empty() || !other.colors.is_empty() {
+            // meshes without colors are white, so the colors stay aligned with positions
+            self.colors.resize(3 * first_vertex as usize, 1.0);
+            self.colors.extend(other.colors);
+            self.colors.resize(3 * vertex_count, 1.0);
+        }
+        self.models
+            .extend(other.models.into_iter().map(|model| Model {
+                indices_start: model.indices_start + first_triangle,
+                indices_end: model.indices_end + first_triangle,
+                material_idx: if model.material_idx == NO_MATERIAL {
+                    NO_MATERIAL
+                } else {
+                    model.material_idx + material_offset
+                },
+            }));
+        self.indices
+            .extend(other.indices.into_iter().map(|i| i + first_vertex));
+
+        other.transform(transform);
+        self.positions.extend(other.positions);
+        self.normals.extend(other.normals);
+        self.texcoords.extend(other.texcoords);
+    }
+
+    /// Transforms the positions and normals in place
+    fn transform(&mut self, transform: &cgmath::Matrix4<f32>) {
+        let normal_transform = normal_matrix(transform);
+        for p in self.positions.chunks_mut(3) {
+            let t = transform * cgmath::Vector4::new(p[0], p[1], p[2], 1.0);
+            p.copy_from_slice(&[t.x, t.y, t.z]);
+        }
+        for n in self.normals.chunks_mut(3) {
+            let t = normal_transform * cgmath::Vector3::new(n[0], n[1], n[2]);
+            let t = if t.magnitude2() > 0.0 { t.normalize() } else { t };
+            n.copy_from_slice(&[t.x, t.y, t.z]);
+        }
+    }
+
+    /// Material of every triangle, so the shader doesn't search for the model
+    /// containing the hit triangle
+    fn triangle_materials(&self) -> Vec<u32> {
+        let mut triangle_materials = vec![0; self.indices.len() / 3];
+        for model in &self.models {
+            let range = model.indices_start as usize..model.indices_end as usize;
+            for material_idx in &mut triangle_materials[range] {
+                *material_idx = model.material_idx as u32;
+            }
+        }
+        triangle_materials
+    }
+}
+
+impl ModelBuffers {
+    pub fn from_loaded(
+        scene: LoadedScene,
+        device: Arc<vulkano::device::Device>,
+        queue: Arc<vulkano::device::Queue>,
+    ) -> Result<(ModelBuffers, Box<vulkano::sync::GpuFuture>), SceneError> {
+        let LoadedScene {
+            mesh,
+            materials,
+            textures,
+            lights,
+            instances,
+            instance_nodes,
+            primitives,
+            max_anisotropy,
+            environment,
+            ..
+        } = scene;
+        let max_page_size = device
+            .physical_device()
+            .limits()
+            .max_image_dimension_2d();
+        let linear = linear_textures(&materials, textures.len());
+        let atlas = Atlas::new(textures, &linear, max_page_size);
+        let (textures, textures_future) =
+            upload_textures(device.clone(), queue.clone(), atlas.pages, &atlas.linear)?;
+        let sampler = create_sampler(device.clone(), max_anisotropy);
+        let (environment_map, environment_map_future) =
+            upload_environment(environment.as_ref(), queue.clone())?;
+        let environment_sampler = create_environment_sampler(device);
+        let (buffer_environment, environment_future) = vulkano::buffer::ImmutableBuffer::from_data(
+            tracers::ty::Environment {
+                environment_rotation: environment.as_ref().map_or(0.0, |e| e.rotation),
+                environment_intensity: environment.as_ref().map_or(0.0, |e| e.intensity),
+            },
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        )?;
+        let (buffer_texture_rects, texture_rects_future) =
+            vulkano::buffer::ImmutableBuffer::from_iter(
+                // buffer can't be empty, so there is at least one rect
+                atlas.rects.into_iter().chain(iter::once(tracers::ty::TextureRect {
+                    offset: [0.0; 2],
+                    size: [1.0; 2],
+                    page: 0,
+                    _dummy0: [0; 12],
+                })),
+                vulkano::buffer::BufferUsage {
+                    storage_buffer: true,
+                    ..vulkano::buffer::BufferUsage::none()
+                },
+                queue.clone(),
+            )?;
+
+        let (buffer_triangle_materials, triangle_materials_future) =
+            vulkano::buffer::ImmutableBuffer::from_iter(
+                mesh.triangle_materials().into_iter(),
+                vulkano::buffer::BufferUsage {
+                    storage_buffer: true,
+                    ..vulkano::buffer::BufferUsage::none()
+                },
+                queue.clone(),
+            )?;
+        let model_count = mesh.models.len();
+
+        let (buffer_positions, positions_future) =
+            to_buffer_vec3::<f32, Vec3>(queue.clone(), &mesh.positions)?;
+        let (buffer_indices, indices_future) =
+            to_buffer_vec3::<u32, UVec3>(queue.clone(), &mesh.indices)?;
+        let (buffer_normals, normals_future) =
+            to_buffer_vec3::<f32, Vec3>(queue.clone(), &mesh.normals)?;
+        let (buffer_texcoords, texcoords_future) =
+            to_buffer_vec2::<f32, Vec2>(queue.clone(), &mesh.texcoords)?;
+        let (buffer_colors, colors_future) = if mesh.colors.is_empty() {
+            // buffer can't be empty, no material uses vertex colors anyway
+            to_buffer_vec3::<f32, Vec3>(queue.clone(), &[1.0; 3])?
+        } else {
+            to_buffer_vec3::<f32, Vec3>(queue.clone(), &mesh.colors)?
+        };
+        let (buffer_materials, materials_future) = vulkano::buffer::ImmutableBuffer::from_iter(
+            materials.into_iter(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        )?;
+        let (buffer_lights, lights_future) = vulkano::buffer::ImmutableBuffer::from_iter(
+            lights.into_iter(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        )?;
+        let (buffer_instances, instances_future) = vulkano::buffer::ImmutableBuffer::from_iter(
+            // buffer can't be empty, so there is at least one instance,
+            // which is never referenced by the tree
+            instances.into_iter().chain(iter::once(tracers::ty::Instance {
+                world_to_object: cgmath::Matrix4::identity().into(),
+                bbox_min: [0.0; 3],
+                triangles_start: 0,
+                bbox_max: [0.0; 3],
+                triangles_end: 0,
+            })),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        )?;
+        let primitives_bbox = primitives.bbox();
+        let primitive_count = primitives.bounds.len();
+        let mut object_bounds = triangle_bounds(&mesh.positions, &mesh.indices);
+        object_bounds.extend(primitives.bounds.iter().cloned());
+        let mut primitive_data = primitives.primitives;
+        if primitive_data.is_empty() {
+            // buffer can't be empty, zero sized sphere is never inserted into the grid
+            // and isn't a plane, so it is never intersected
+            primitive_data.push(tracers::ty::Primitive {
+                center: [0.0; 3],
+                kind: 0,
+                normal: [0.0; 3],
+                radius: 0.0,
+                half_size: [0.0; 3],
+                material_idx: 0,
+            });
+        }
+        let (buffer_primitives, primitives_future) = vulkano::buffer::ImmutableBuffer::from_iter(
+            primitive_data.into_iter(),
+            vulkano::buffer::BufferUsage {
+                storage_buffer: true,
+                ..vulkano::buffer::BufferUsage::none()
+            },
+            queue.clone(),
+        )?;
+        let (buffer_primitive_bounds, primitive_bounds_future) =
+            vulkano::buffer::ImmutableBuffer::from_iter(
+                // buffer can't be empty, only the first `primitive_count` bounds are read
+                primitives
+                    .bounds
+                    .into_iter()
+                    .chain(iter::once(([0.0; 3], [0.0; 3])))
+                    .flat_map(|(min, max)| vec![Vec3::from_arr3(min), Vec3::from_arr3(max)]),
+                vulkano::buffer::BufferUsage {
+                    storage_buffer: true,
+                    ..vulkano::buffer::BufferUsage::none()
+                },
+                queue.clone(),
+            )?;
+        let (buffer_instance_nodes, instance_nodes_future) =
+            vulkano::buffer::ImmutableBuffer::from_iter(
+                instance_nodes.into_iter(),
+                vulkano::buffer::BufferUsage {
+                    storage_buffer: true,
+                    ..vulkano::buffer::BufferUsage::none()
+                },
+                queue.clone(),
+            )?;
+
+        let future = Box::new(
+            textures_future
+                .join(positions_future)
+                .join(indices_future)
+                .join(normals_future)
+                .join(texcoords_future)
+                .join(colors_future)
+                .join(materials_future)
+                .join(texture_rects_future)
+                .join(lights_future)
+                .join(instances_future)
+                .join(instance_nodes_future)
+                .join(triangle_materials_future)
+                .join(environment_map_future)
+                .join(environment_future)
+                .join(primitives_future)
+                .join(primitive_bounds_future),
+        ) as Box<_>;
+
+        Ok((
+            ModelBuffers {
+                positions: buffer_positions,
+                indices: buffer_indices,
+                normals: buffer_normals,
+                texcoords: buffer_texcoords,
+                colors: buffer_colors,
+                materials: buffer_materials,
+                textures: textures,
+                sampler,
+                environment_map,
+                environment_sampler,
+                environment: buffer_environment,
+                texture_rects: buffer_texture_rects,
+                lights: buffer_lights,
+                instances: buffer_instances,
+                instance_nodes: buffer_instance_nodes,
+                triangle_materials: buffer_triangle_materials,
+                primitives: buffer_primitives,
+                primitive_bounds: buffer_primitive_bounds,
+                primitives_bbox,
+                model_count,
+                triangle_count: mesh.indices.len() / 3,
+                primitive_count,
+                object_bounds,
+            },
+            future,
+        ))
+    }
+
+    pub fn build_descriptor_set(
+        &self,
+        pipeline: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
+        set_id: usize,
+    ) -> Result<
+        Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
+        vulkano::descriptor::descriptor_set::PersistentDescriptorSetError,
+    > {
+        let sampler = self.sampler.clone();
+        let ds = vulkano::descriptor::descriptor_set::PersistentDescriptorSet::start(
+            pipeline.clone(),
+            set_id,
+        ).add_buffer(self.positions.clone())?
+            .add_buffer(self.indices.clone())?
+            .add_buffer(self.normals.clone())?
+            .add_buffer(self.texcoords.clone())?
+            .add_buffer(self.materials.clone())?
+            .enter_array()?
+            .add_sampled_image(self.textures[0].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[1].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[2].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[3].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[4].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[5].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[6].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[7].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[8].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[9].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[10].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[11].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[12].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[13].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[14].clone(), sampler.clone())?
+            .add_sampled_image(self.textures[15].clone(), sampler.clone())?
+            .leave_array()?
+            .add_buffer(self.lights.clone())?
+            .add_buffer(self.texture_rects.clone())?
+            .add_buffer(self.instances.clone())?
+            .add_buffer(self.instance_nodes.clone())?
+            .add_buffer(self.triangle_materials.clone())?
+            .add_sampled_image(self.environment_map.clone(), self.environment_sampler.clone())?
+            .add_buffer(self.environment.clone())?
+            .add_buffer(self.primitives.clone())?
+            .add_buffer(self.colors.clone())?
+            .build()
+            .expect("failed to build scene descriptor set");
+        Ok(Arc::new(ds))
+    }
+}
+
+/// Loads the model from the cache, if it is up to date, or from the model file
+/// otherwise. Cache problems are not fatal, the model is just loaded from scratch.
+fn load_cached_model(
+    model: &ModelDescription,
+    description: &SceneDescription,
+) -> Result<LoadedModel, SceneError> {
+    if !description.cache.enabled {
+        return load_model(model, &description.texture_paths);
+    }
+    let key = cache::Key::new(model, &description.texture_paths);
+    if !description.cache.rebuild {
+        match cache::read(&key) {
+            Ok(Some(loaded)) => return Ok(loaded),
+            Ok(None) => (),
+            Err(e) => println!("warning: can't read cache of {:?}: {}", model.path, e),
+        }
+    }
+    let loaded = load_model(model, &description.texture_paths)?;
+    if let Err(e) = cache::write(&key, &loaded) {
+        println!("warning: can't write cache of {:?}: {}", model.path, e);
+    }
+    Ok(loaded)
+}
+
+fn load_model(
+    model: &ModelDescription,
+    search_paths: &[PathBuf],
+) -> Result<LoadedModel, SceneError> {
+    let path = &model.path;
+    let mut loaded = match path.extension().and_then(|e| e.to_str()) {
+        Some("obj") => load_obj(model, search_paths)?,
+        Some("gltf") | Some("glb") => gltf::load(model).map_err(|e| match e {
+                ::gltf::Error::Io(error) => SceneError::Io {
+                    path: path.clone(),
+                    error,
+                },
+                e => SceneError::Parse {
+                    path: path.clone(),
+                    message: e.to_string(),
+                },
+            })?,
+        Some("ply") => ply::load(path)
+            .map(|loaded| LoadedModel::from_single_part(loaded, model))
+            .map_err(|e| SceneError::from_io(path.clone(), e))?,
+        Some("stl") => stl::load(path)
+            .map(|loaded| LoadedModel::from_single_part(loaded, model))
+            .map_err(|e| SceneError::from_io(path.clone(), e))?,
+        _ => {
+            return Err(SceneError::Unsupported {
+                path: path.clone(),
+                feature: String::from("model format"),
+            })
+        }
+    };
+    // converted before the buffers are built, so the structures are built
+    // over the vertices in the conventions of the scene
+    let (min, max) = bounds(&loaded.mesh.positions);
+    let conversion = model.conversion([
+        0.5 * (min[0] + max[0]),
+        0.5 * (min[1] + max[1]),
+        0.5 * (min[2] + max[2]),
+    ]);
+    if conversion != cgmath::Matrix4::identity() {
+        loaded.mesh.transform(&conversion);
+    }
+    Ok(loaded)
+}
+
+/// Validates the part of the model and generates its missing attributes,
+/// so it can be appended to the other parts
+fn prepare_part(part: &mut Mesh, description: &ModelDescription, report: &mut validate::Report) {
+    report.add(&validate::validate(part, description.repair));
+    generate::fill_missing_attributes(part, description.normals, description.crease_angle);
+}
+
+fn load_obj(
+    model: &ModelDescription,
+    search_paths: &[PathBuf],
+) -> Result<LoadedModel, SceneError> {
+    let base_dir = model.path.parent().unwrap_or(Path::new(""));
+    // the text is read once, both tobj and the vertex colors parse it
+    let mut text = String::new();
+    File::open(&model.path)
+        .and_then(|mut file| file.read_to_string(&mut text))
+        .map_err(|error| SceneError::Io {
+            path: model.path.clone(),
+            error,
+        })?;
+    // tobj can't report why material library wasn't loaded, so remember it here
+    let mtl_error = RefCell::new(None);
+    let mtl_paths = RefCell::new(Vec::new());
+    let result = tobj::load_obj_buf(&mut text.as_bytes(), |mtl_path| {
+        let found = find_file(&mtl_path.to_string_lossy(), base_dir, search_paths);
+        match found {
+            Ok(path) => {
+                mtl_paths.borrow_mut().push(path.clone());
+                tobj::load_mtl(&path).map_err(|e| {
+                    *mtl_error.borrow_mut() = Some(SceneError::Parse {
+                        path: path.clone(),
+                        message: format!("{:?}", e),
+                    });
+                    e
+                })
+            }
+            Err(e) => {
+                *mtl_error.borrow_mut() = Some(e);
+                Err(tobj::LoadError::OpenFileFailed)
+            }
+        }
+    });
+    let (obj_models, obj_materials) = match (result, mtl_error.into_inner()) {
+        (_, Some(e)) => return Err(e),
+        (Ok(result), None) => result,
+        (Err(e), None) => {
+            return Err(SceneError::Parse {
+                path: model.path.clone(),
+                message: format!("{:?}", e),
+            })
+        }
+    };
+    let mut textures = TextureSet::new(base_dir, search_paths);
+    let mut materials = load_materials(obj_materials, &mut textures)?;
+    let colors = ObjColors::parse(&text).for_models(&obj_models, &model.path);
+    let vertex_color_material_idx = materials.len() as i32;
+    let (mesh, report) = load_mesh(obj_models, colors, vertex_color_material_idx, model);
+    if mesh.models
+        .iter()
+        .any(|m| m.material_idx == vertex_color_material_idx)
+    {
+        materials.push(vertex_color_material());
+    }
+    let mut dependencies = mtl_paths.into_inner();
+    dependencies.extend(textures.paths);
+    Ok(LoadedModel {
+        mesh,
+        materials,
+        textures: textures.images,
+        dependencies,
+        report,
+    })
+}
+
+fn load_lights(descriptions: &[LightDescription]) -> Vec<tracers::ty::Light> {
+    if descriptions.is_empty() {
+        // without lights in the scene, it is lit from the camera
+        return vec![
+            tracers::ty::Light {
+                position: [0.0; 3],
+                kind: LIGHT_HEADLIGHT,
+                color: [1.0; 3],
+                intensity: 1.0,
+            },
+        ];
+    }
+    descriptions
+        .iter()
+        .map(|description| match *description {
+            LightDescription::Point {
+                position,
+                color,
+                intensity,
+            } => tracers::ty::Light {
+                position,
+                kind: LIGHT_POINT,
+                color,
+                intensity,
+            },
+            LightDescription::Directional {
+                direction,
+                color,
+                intensity,
+            } => tracers::ty::Light {
+                position: direction,
+                kind: LIGHT_DIRECTIONAL,
+                color,
+                intensity,
+            },
+        })
+        .collect()
+}
+
+fn offset_texture_indices(material: tracers::ty::Material, offset: i32) -> tracers::ty::Material {
+    let offset_idx = |idx: i32| if idx < 0 { idx } else { idx + offset };
+    tracers::ty::Material {
+        ambient_texture_idx: offset_idx(material.ambient_texture_idx),
+        diffuse_texture_idx: offset_idx(material.diffuse_texture_idx),
+        specular_texture_idx: offset_idx(material.specular_texture_idx),
+        normal_texture_idx: offset_idx(material.normal_texture_idx),
+        disolve_texture_idx: offset_idx(material.disolve_texture_idx),
+        ..material
+    }
+}
+
+/// Bounding box of the flattened positions, zero sized for the empty mesh
+fn bounds(positions: &[f32]) -> ([f32; 3], [f32; 3]) {
+    if positions.is_empty() {
+        return ([0.0; 3], [0.0; 3]);
+    }
+    let mut min = [::std::f32::INFINITY; 3];
+    let mut max = [::std::f32::NEG_INFINITY; 3];
+    for p in positions.chunks(3) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(p[axis]);
+            max[axis] = max[axis].max(p[axis]);
+        }
+    }
+    (min, max)
+}
+
+fn normal_matrix(transform: &cgmath::Matrix4<f32>) -> cgmath::Matrix3<f32> {
+    let m = transform;
+    cgmath::Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate())
+        .invert()
+        .map(|m| m.transpose())
+        .unwrap_or(cgmath::Matrix3::identity())
+}
+
+fn load_materials(
+    obj_materials: Vec<tobj::Material>,
+    textures: &mut TextureSet,
+) -> Result<Vec<tracers::ty::Material>, SceneError> {
+    obj_materials
+        .iter()
+        .map(|m| load_material(m, textures))
+        .collect()
+}
+
+/// Textures of the model, each file is loaded only once
+/// even if it is used by several materials
+struct TextureSet<'a> {
+    images: Vec<image::RgbaImage>,
+    paths: Vec<PathBuf>,
+    indices: HashMap<(String, TextureKind), i32>,
+    base_dir: &'a Path,
+    search_paths: &'a [PathBuf],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum TextureKind {
+    Color,
+    Normal,
+    /// Scalar map, which isn't sRGB
+    Dissolve,
+}
+
+impl<'a> TextureSet<'a> {
+    fn new(base_dir: &'a Path, search_paths: &'a [PathBuf]) -> TextureSet<'a> {
+        TextureSet {
+            images: Vec::new(),
+            paths: Vec::new(),
+            indices: HashMap::new(),
+            base_dir,
+            search_paths,
+        }
+    }
+
+    /// Returns index of the texture or -1 if there is no texture in the MTL statement
+    fn load(&mut self, statement: &str, kind: TextureKind) -> Result<i32, SceneError> {
+        let name = match texture_name(statement) {
+            Some(name) => name.to_string(),
+            None => return Ok(-1),
+        };
+        let key = (name, kind);
+        if let Some(&idx) = self.indices.get(&key) {
+            return Ok(idx);
+        }
+        let path = find_file(&key.0, self.base_dir, self.search_paths)?;
+        let mut texture = load_texture(&path)?;
+        if kind == TextureKind::Normal && is_grayscale(&texture) {
+            // bump maps are height maps, but shader expects normal maps
+            texture = height_to_normal_map(&texture);
+        }
+        let idx = self.images.len() as i32;
+        self.images.push(texture);
+        self.paths.push(path);
+        self.indices.insert(key, idx);
+        Ok(idx)
+    }
+}
+
+/// File name of the MTL texture statement, it follows the options and may contain spaces,
+/// e.g. `bump map.png` in `-bm 0.5 -o 0 0.5 bump map.png`
+fn texture_name(statement: &str) -> Option<&str> {
+    let mut rest = statement.trim();
+    while rest.starts_with('-') {
+        let (option, after) = next_word(rest);
+        rest = after;
+        // offset, scale and turbulence take up to three numbers, the rest a single value
+        let (min_args, max_args) = match option {
+            "-o" | "-s" | "-t" => (1, 3),
+            "-mm" => (2, 2),
+            _ => (1, 1),
+        };
+        for i in 0..max_args {
+            let (arg, after) = next_word(rest);
+            if i >= min_args && arg.parse::<f32>().is_err() {
+                break;
+            }
+            rest = after;
+        }
+    }
+    if rest.is_empty() {
+        None
+    } else {
+        Some(rest)
+    }
+}
+
+/// The first word and the rest of the string after the whitespace following it
+fn next_word(s: &str) -> (&str, &str) {
+    let s = s.trim_left();
+    match s.find(char::is_whitespace) {
+        Some(end) => (&s[..end], s[end..].trim_left()),
+        None => (s, ""),
+    }
+}
+
+/// Textures, which are referenced by the materials as normal or dissolve maps. Their data
+/// isn't color, so it is sampled as it is. Every file is loaded for each kind separately,
+/// so a texture isn't both color and linear.
+fn linear_textures(materials: &[tracers::ty::Material], texture_count: usize) -> Vec<bool> {
+    let mut linear = vec![false; texture_count];
+    for material in materials {
+        for &idx in &[material.normal_texture_idx, material.disolve_texture_idx] {
+            if idx >= 0 {
+                linear[idx as usize] = true;
+            }
+        }
+    }
+    linear
+}
+
+/// Looks for the file referenced by the model in the directory of the model
+/// and then in the search paths. Models exported on other machines often
+/// reference absolute paths, so the bare file name is tried too.
+fn find_file(
+    name: &str,
+    base_dir: &Path,
+    search_paths: &[PathBuf],
+) -> Result<PathBuf, SceneError> {
+    let name = name.replace('\\', "/");
+    let relative = Path::new(&name);
+    let mut names = vec![relative];
+    if let Some(file_name) = relative.file_name() {
+        if file_name != relative.as_os_str() {
+            names.push(Path::new(file_name));
+        }
+    }
+
+    let mut tried = Vec::new();
+    for name in names {
+        for dir in iter::once(base_dir).chain(search_paths.iter().map(|p| p.as_path())) {
+            let path = dir.join(name);
+            if path.is_file() {
+                return Ok(path);
+            }
+            if !tried.contains(&path) {
+                tried.push(path);
+            }
+        }
+    }
+    Err(SceneError::NotFound { name, tried })
+}
+
+/// Trilinear sampler, anisotropic if it's requested and supported by the device
+fn create_sampler(
+    device: Arc<vulkano::device::Device>,
+    max_anisotropy: f32,
+) -> Arc<vulkano::sampler::Sampler> {
+    let max_anisotropy = if max_anisotropy <= 1.0 {
+        1.0
+    } else if !device.enabled_features().sampler_anisotropy {
+        println!("warning: anisotropic filtering is not supported by the device");
+        1.0
+    } else {
+        max_anisotropy.min(device.physical_device().limits().max_sampler_anisotropy())
+    };
+    vulkano::sampler::Sampler::new(
+        device,
+        vulkano::sampler::Filter::Linear,
+        vulkano::sampler::Filter::Linear,
+        vulkano::sampler::MipmapMode::Linear,
+        vulkano::sampler::SamplerAddressMode::Repeat,
+        vulkano::sampler::SamplerAddressMode::Repeat,
+        vulkano::sampler::SamplerAddressMode::Repeat,
+        0.0,
+        max_anisotropy,
+        0.0,
+        1000.0,
+    ).expect("failed to create sampler")
+}
+
+/// Wraps around horizontally, but not over the poles
+fn create_environment_sampler(
+    device: Arc<vulkano::device::Device>,
+) -> Arc<vulkano::sampler::Sampler> {
+    vulkano::sampler::Sampler::new(
+        device,
+        vulkano::sampler::Filter::Linear,
+        vulkano::sampler::Filter::Linear,
+        vulkano::sampler::MipmapMode::Linear,
+        vulkano::sampler::SamplerAddressMode::Repeat,
+        vulkano::sampler::SamplerAddressMode::ClampToEdge,
+        vulkano::sampler::SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        1000.0,
+    ).expect("failed to create sampler")
+}
+
+/// Uploads atlas pages and fills the rest of texture slots with empty images
+fn upload_textures(
+    device: Arc<vulkano::device::Device>,
+    queue: Arc<vulkano::device::Queue>,
+    images: Vec<image::RgbaImage>,
+    linear: &[bool],
+) -> Result<
+    (
+        Vec<Arc<vulkano::image::ImageViewAccess + Send + Sync>>,
+        Box<vulkano::sync::GpuFuture>,
+    ),
+    SceneError,
+> {
+    let mut textures: Vec<Arc<vulkano::image::ImageViewAccess + Send + Sync>> =
+        Vec::with_capacity(atlas::MAX_PAGES);
+    let mut future = Box::new(vulkano::sync::now(device)) as Box<vulkano::sync::GpuFuture>;
+    for (image, &linear) in images.into_iter().zip(linear) {
+        let (texture, f) = upload_texture(image, linear, queue.clone())?;
+        textures.push(texture);
+        future = Box::new(future.join(f));
+    }
+    let (ei, f) = empty_image(queue.clone())?;
+    future = Box::new(future.join(f));
+    for _ in textures.len()..atlas::MAX_PAGES {
+        textures.push(ei.clone());
+    }
+    Ok((textures, future))
+}
+
+/// Colors of the vertices from the `v x y z r g b` extension of the format, which tobj
+/// ignores. tobj numbers vertices of every model in the order they first appear in its
+/// faces, so the same numbering is repeated here to find the position of every vertex.
+struct ObjColors {
+    positions: Vec<[f32; 3]>,
+    /// Color of every position, empty if no vertex has a color
+    colors: Vec<[f32; 3]>,
+    /// Position indices of the vertices of every model in the order of tobj
+    models: Vec<Vec<usize>>,
+}
+
+impl ObjColors {
+    fn parse(text: &str) -> ObjColors {
+        let mut positions = Vec::new();
+        let mut colors = Vec::new();
+        let mut has_colors = false;
+        let mut texcoord_count = 0;
+        let mut normal_count = 0;
+        let mut models = Vec::new();
+        let mut vertices = Vec::new();
+        let mut seen = HashSet::new();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    // the color follows the position, a comment may follow the color
+                    let values: Vec<f32> = words
+                        .map(str::parse::<f32>)
+                        .take_while(Result::is_ok)
+                        .filter_map(Result::ok)
+                        .collect();
+                    if values.len() < 3 {
+                        // tobj rejects the file anyway
+                        continue;
+                    }
+                    positions.push([values[0], values[1], values[2]]);
+                    if values.len() >= 6 {
+                        has_colors = true;
+                        colors.push([values[3], values[4], values[5]]);
+                    } else {
+                        colors.push([1.0; 3]);
+                    }
+                }
+                Some("vt") => texcoord_count += 1,
+                Some("vn") => normal_count += 1,
+                Some("f") => for word in words {
+                    let counts = [positions.len(), texcoord_count, normal_count];
+                    if let Some(vertex) = face_vertex(word, counts) {
+                        if seen.insert(vertex) {
+                            vertices.push(vertex[0] as usize);
+                        }
+                    }
+                },
+                // like in tobj, an object or a group ends the model, if it has faces
+                Some("o") | Some("g") => if !seen.is_empty() {
+                    models.push(mem::replace(&mut vertices, Vec::new()));
+                    seen.clear();
+                },
+                _ => {}
+            }
+        }
+        // tobj returns the last model even without faces
+        models.push(vertices);
+        if !has_colors {
+            colors.clear();
+        }
+        ObjColors {
+            positions,
+            colors,
+            models,
+        }
+    }
+
+    /// Colors of the vertices of every model, empty if the file doesn't have them.
+    /// They are ignored, if tobj didn't number the vertices as expected.
+    fn for_models(&self, obj_models: &[tobj::Model], path: &Path) -> Vec<Vec<f32>> {
+        if !self.colors.is_empty() {
+            if self.matches(obj_models) {
+                return self.models
+                    .iter()
+                    .map(|vertices| {
+                        vertices
+                            .iter()
+                            .flat_map(|&idx| self.colors[idx].to_vec())
+                            .collect()
+                    })
+                    .collect();
+            }
+            println!(
+                "warning: {}: vertex colors are ignored, vertices aren't in the expected order",
+                path.display()
+            );
+        }
+        vec![Vec::new(); obj_models.len()]
+    }
+
+    fn matches(&self, obj_models: &[tobj::Model]) -> bool {
+        self.models.len() == obj_models.len()
+            && self.models
+                .iter()
+                .zip(obj_models)
+                .all(|(vertices, obj_model)| {
+                    let obj_positions = &obj_model.mesh.positions;
+                    obj_positions.len() == 3 * vertices.len()
+                        && vertices
+                            .iter()
+                            .zip(obj_positions.chunks(3))
+                            .all(|(&idx, p)| {
+                                self.positions.get(idx).map_or(false, |q| q[..] == p[..])
+                            })
+                })
+    }
+}
+
+/// Indices of the position, texture coordinates and normal of the face vertex, e.g. `1/2/3`
+/// or `-1//1`, resolved the same way as in tobj. The missing ones are -1.
+fn face_vertex(word: &str, counts: [usize; 3]) -> Option<[isize; 3]> {
+    let mut indices = [-1; 3];
+    for (i, index) in word.split('/').take(3).enumerate() {
+        if index.is_empty() {
+            continue;
+        }
+        let index: isize = index.parse().ok()?;
+        // negative indices count back from the last element defined so far
+        indices[i] = if index < 0 {
+            counts[i] as isize + index
+        } else {
+            index - 1
+        };
+    }
+    Some(indices)
+}
+
+fn load_mesh(
+    obj_models: Vec<tobj::Model>,
+    colors: Vec<Vec<f32>>,
+    vertex_color_material_idx: i32,
+    description: &ModelDescription,
+) -> (Mesh, validate::Report) {
+    let mut mesh = Mesh::new();
+    let mut report = validate::Report::default();
+    for (obj_model, mesh_colors) in obj_models.into_iter().zip(colors) {
+        let obj_mesh = obj_model.mesh;
+        // meshes without `usemtl` get the default material of the scene,
+        // or are painted with their vertex colors
+        let material_idx = match obj_mesh.material_id {
+            Some(id) => id as i32,
+            None if !mesh_colors.is_empty() => vertex_color_material_idx,
+            None => NO_MATERIAL,
+        };
+        let mut part = Mesh {
+            models: vec![
+                Model {
+                    indices_start: 0,
+                    indices_end: obj_mesh.indices.len() as u32 / 3,
+                    material_idx: material_idx,
+                },
+            ],
+            positions: obj_mesh.positions,
+            indices: obj_mesh.indices,
+            normals: obj_mesh.normals,
+            texcoords: obj_mesh.texcoords,
+            colors: mesh_colors,
+        };
+        // some groups of the file may have normals and texture coordinates,
+        // while others don't, so they are generated for every group separately
+        prepare_part(&mut part, description, &mut report);
+        mesh.append(part, &cgmath::Matrix4::identity(), 0);
+    }
+    (mesh, report)
+}
+
+fn empty_image(
+    queue: Arc<vulkano::device::Queue>,
+) -> Result<
+    (
+        Arc<vulkano::image::ImmutableImage<vulkano::format::R8G8B8A8Srgb>>,
+        Box<vulkano::sync::GpuFuture>,
+    ),
+    SceneError,
+> {
+    let pixel = vec![255u8; 4];
+    let (texture, future) = vulkano::image::immutable::ImmutableImage::from_iter(
+        pixel.into_iter(),
+        vulkano::image::Dimensions::Dim2d {
+            width: 1,
+            height: 1,
+        },
+        vulkano::format::R8G8B8A8Srgb,
+        queue,
+    )?;
+    Ok((texture, Box::new(future)))
+}
+
+fn load_texture(path: &Path) -> Result<image::RgbaImage, SceneError> {
+    match image::open(path) {
+        Ok(image) => Ok(image.to_rgba()),
+        Err(image::ImageError::IoError(error)) => Err(SceneError::Io {
+            path: path.to_path_buf(),
+            error,
+        }),
+        Err(error) => Err(SceneError::ImageDecode {
+            path: path.to_path_buf(),
+            error,
+        }),
+    }
+}
+
+/// Linear pages are UNORM, so the shader reads their data without sRGB decoding
+fn upload_texture(
+    image: image::RgbaImage,
+    linear: bool,
+    queue: Arc<vulkano::device::Queue>,
+) -> Result<
+    (
+        Arc<vulkano::image::ImageViewAccess + Send + Sync>,
+        Box<vulkano::sync::GpuFuture>,
+    ),
+    SceneError,
+> {
+    let levels = mipmaps::generate(image, linear)
+        .into_iter()
+        .map(|level| (level.width(), level.height(), level.into_raw()))
+        .collect();
+    Ok(if linear {
+        let (texture, future) = upload_levels(levels, vulkano::format::R8G8B8A8Unorm, queue)?;
+        (
+            texture as Arc<vulkano::image::ImageViewAccess + Send + Sync>,
+            future,
+        )
+    } else {
+        let (texture, future) = upload_levels(levels, vulkano::format::R8G8B8A8Srgb, queue)?;
+        (
+            texture as Arc<vulkano::image::ImageViewAccess + Send + Sync>,
+            future,
+        )
+    })
+}
+
+/// Environment map is uploaded even if the scene doesn't have it, so the descriptor
+/// set is the same. The black pixel with zero intensity neither lights nor shows.
+fn upload_environment(
+    environment: Option<&environment::EnvironmentMap>,
+    queue: Arc<vulkano::device::Queue>,
+) -> Result<
+    (
+        Arc<vulkano::image::ImmutableImage<vulkano::format::R32G32B32A32Sfloat>>,
+        Box<vulkano::sync::GpuFuture>,
+    ),
+    SceneError,
+> {
+    let levels = match environment {
+        Some(environment) => environment
+            .levels
+            .iter()
+            .map(|&(width, height, ref pixels)| {
+                let components = pixels.iter().flat_map(|p| p.iter().cloned()).collect();
+                (width, height, components)
+            })
+            .collect(),
+        None => vec![(1, 1, vec![0.0; 4])],
+    };
+    upload_levels(levels, vulkano::format::R32G32B32A32Sfloat, queue)
+}
+
+/// Uploads the image with all of its mip levels, given as dimensions and components
+fn upload_levels<F, T>(
+    levels: Vec<(u32, u32, Vec<T>)>,
+    format: F,
+    queue: Arc<vulkano::device::Queue>,
+) -> Result<(Arc<vulkano::image::ImmutableImage<F>>, Box<vulkano::sync::GpuFuture>), SceneError>
+where
+    F: vulkano::format::FormatDesc + Send + Sync + 'static,
+    T: Send + Sync + 'static,
+    vulkano::format::Format: vulkano::format::AcceptsPixels<T>,
+{
+    let (width, height) = (levels[0].0, levels[0].1);
+    let (texture, init) = vulkano::image::immutable::ImmutableImage::uninitialized(
+        queue.device().clone(),
+        vulkano::image::Dimensions::Dim2d { width, height },
+        format,
+        vulkano::image::MipmapsCount::Specific(levels.len() as u32),
+        vulkano::image::ImageUsage {
+            transfer_destination: true,
+            sampled: true,
+            ..vulkano::image::ImageUsage::none()
+        },
+        vulkano::image::ImageLayout::ShaderReadOnlyOptimal,
+        Some(queue.family()),
+    )?;
+    let init = Arc::new(init);
+
+    let mut cbb = vulkano::command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
+        queue.device().clone(),
+        queue.family(),
+    )?;
+    for (level, (width, height, components)) in levels.into_iter().enumerate() {
+        let source = vulkano::buffer::CpuAccessibleBuffer::from_iter(
+            queue.device().clone(),
+            vulkano::buffer::BufferUsage::transfer_source(),
+            components.into_iter(),
+        )?;
+        cbb = cbb.copy_buffer_to_image_dimensions(
+            source,
+            init.clone(),
+            [0, 0, 0],
+            [width, height, 1],
+            0,
+            1,
+            level as u32,
+        ).expect("failed to copy mip level");
+    }
+    let cb = cbb.build()
+        .expect("failed to build image upload command buffer");
+    let future = vulkano::sync::now(queue.device().clone())
+        .then_execute(queue, cb)
+        .expect("failed to upload image");
+    Ok((texture, Box::new(future)))
+}
+
+fn load_material(
+    material: &tobj::Material,
+    textures: &mut TextureSet,
+) -> Result<tracers::ty::Material, SceneError> {
+    // tobj knows only `norm` statement, bump maps end up in unknown parameters
+    let normal_texture = if material.normal_texture != "" {
+        Some(&material.normal_texture)
+    } else {
+        ["map_Bump", "map_bump", "bump"]
+            .iter()
+            .filter_map(|name| material.unknown_param.get(*name))
+            .next()
+    };
+    let normal_texture_idx = match normal_texture {
+        Some(texture) => textures.load(texture, TextureKind::Normal)?,
+        None => -1,
+    };
+    let diffuse_texture_idx = textures.load(&material.diffuse_texture, TextureKind::Color)?;
+    Ok(tracers::ty::Material {
+        ambient: material.ambient,
+        diffuse: material.diffuse,
+        specular: material.specular,
+        shininess: material.shininess,
+        dissolve: material.dissolve,
+        optical_density: material.optical_density,
+        ambient_texture_idx: textures.load(&material.ambient_texture, TextureKind::Color)?,
+        diffuse_texture_idx,
+        specular_texture_idx: textures.load(&material.specular_texture, TextureKind::Color)?,
+        normal_texture_idx,
+        disolve_texture_idx: textures.load(&material.dissolve_texture, TextureKind::Dissolve)?,
+        diffuse_source: diffuse_source(diffuse_texture_idx),
+        _dummy0: [0; 4],
+        _dummy1: [0; 4],
+    })
+}
+
+fn is_grayscale(texture: &image::RgbaImage) -> bool {
+    texture.pixels().all(|p| p[0] == p[1] && p[1] == p[2])
+}
+
+/// Converts height map to tangent space normal map using Sobel operator
+fn height_to_normal_map(heights: &image::RgbaImage) -> image::RgbaImage {
+    const STRENGTH: f32 = 2.0;
+    let (width, height) = heights.dimensions();
+    let h = |x: i64, y: i64| {
+        let x = ((x + width as i64) % width as i64) as u32;
+        let y = ((y + height as i64) % height as i64) as u32;
+        heights.get_pixel(x, y)[0] as f32 / 255.0
+    };
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dx = (h(x + 1, y - 1) + 2.0 * h(x + 1, y) + h(x + 1, y + 1))
+            - (h(x - 1, y - 1) + 2.0 * h(x - 1, y) + h(x - 1, y + 1));
+        let dy = (h(x - 1, y + 1) + 2.0 * h(x, y + 1) + h(x + 1, y + 1))
+            - (h(x - 1, y - 1) + 2.0 * h(x, y - 1) + h(x + 1, y - 1));
+        let normal = cgmath::Vector3::new(-dx * STRENGTH, -dy * STRENGTH, 1.0).normalize();
+        image::Rgba([
+            ((normal.x * 0.5 + 0.5) * 255.0) as u8,
+            ((normal.y * 0.5 + 0.5) * 255.0) as u8,
+            ((normal.z * 0.5 + 0.5) * 255.0) as u8,
+            255,
+        ])
+    })
+}
+
+// must match the constants in tracer.comp.tera
+const LIGHT_HEADLIGHT: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_DIRECTIONAL: u32 = 2;
+
+// must match the constants in tracer.comp.tera
+const DIFFUSE_CONSTANT: u32 = 0;
+const DIFFUSE_TEXTURE: u32 = 1;
+const DIFFUSE_VERTEX: u32 = 2;
+
+/// Diffuse color of the default material, unless the scene overrides it
+const DEFAULT_DIFFUSE: [f32; 3] = [0.8, 0.8, 0.8];
+
+/// Material index of the meshes that don't specify one,
+/// replaced by the default material of the scene
+const NO_MATERIAL: i32 = -1;
+
+fn default_material(diffuse: [f32; 3]) -> tracers::ty::Material {
+    tracers::ty::Material {
+        ambient: [0.0; 3],
+        diffuse,
+        specular: [0.0; 3],
+        shininess: 0.0,
+        dissolve: 1.0,
+        optical_density: 1.0,
+        ambient_texture_idx: -1,
+        diffuse_texture_idx: -1,
+        specular_texture_idx: -1,
+        normal_texture_idx: -1,
+        disolve_texture_idx: -1,
+        diffuse_source: DIFFUSE_CONSTANT,
+        _dummy0: [0; 4],
+        _dummy1: [0; 4],
+    }
+}
+
+/// Material painted with the interpolated colors of the vertices
+fn vertex_color_material() -> tracers::ty::Material {
+    tracers::ty::Material {
+        diffuse_source: DIFFUSE_VERTEX,
+        ..default_material([1.0; 3])
+    }
+}
+
+/// Textured materials use the texture, the rest use the constant color
+fn diffuse_source(diffuse_texture_idx: i32) -> u32 {
+    if diffuse_texture_idx == -1 {
+        DIFFUSE_CONSTANT
+    } else {
+        DIFFUSE_TEXTURE
+    }
+}
+
+fn to_buffer_vec2<'a, T, V>(
+    queue: Arc<vulkano::device::Queue>,
+    vec: &[T],
+) -> Result<
+    (
+        Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        Box<vulkano::sync::GpuFuture>,
+    ),
+    SceneError,
+>
+where
+    V: 'static + FromArr2<T> + Sync + Send,
+    T: Copy,
+{
+    let (buffer, future) = vulkano::buffer::ImmutableBuffer::from_iter(
+        vec.chunks(2)
+            .map(|chunk| V::from_arr2([chunk[0], chunk[1]])),
+        vulkano::buffer::BufferUsage {
+            storage_buffer: true,
+            ..vulkano::buffer::BufferUsage::none()
+        },
+        queue,
+    )?;
+    Ok((buffer, Box::new(future)))
+}
+
+/// Bounds of every triangle of the flattened mesh
+fn triangle_bounds(positions: &[f32], indices: &[u32]) -> Vec<([f32; 3], [f32; 3])> {
+    indices
+        .chunks(3)
+        .map(|triangle| {
+            let mut min = [::std::f32::INFINITY; 3];
+            let mut max = [::std::f32::NEG_INFINITY; 3];
+            for &vertex in triangle {
+                for axis in 0..3 {
+                    let coordinate = positions[3 * vertex as usize + axis];
+                    min[axis] = min[axis].min(coordinate);
+                    max[axis] = max[axis].max(coordinate);
+                }
+            }
+            (min, max)
+        })
+        .collect()
+}
+
+fn to_buffer_vec3<'a, T, V>(
+    queue: Arc<vulkano::device::Queue>,
+    vec: &[T],
+) -> Result<
+    (
+        Arc<vulkano::buffer::BufferAccess + Send + Sync>,
+        Box<vulkano::sync::GpuFuture>,
+    ),
+    SceneError,
+>
+where
+    V: 'static + FromArr3<T> + Sync + Send,
+    T: Copy,
+{
+    let (buffer, future) = vulkano::buffer::ImmutableBuffer::from_iter(
+        vec.chunks(3)
+            .map(|chunk| V::from_arr3([chunk[0], chunk[1], chunk[2]])),
+        vulkano::buffer::BufferUsage {
+            storage_buffer: true,
+            ..vulkano::buffer::BufferUsage::none()
+        },
+        queue,
+    )?;
+    Ok((buffer, Box::new(future)))
+}
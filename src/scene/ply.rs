@@ -0,0 +1,354 @@
+extern crate byteorder;
+extern crate image;
+
+use self::byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+
+use super::{vertex_color_material, Mesh, Model, NO_MATERIAL};
+use tracers;
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::str;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    ty: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+enum Body<'a> {
+    /// Tokens and the number of the tokens left
+    Ascii(str::SplitWhitespace<'a>, usize),
+    LittleEndian(io::Cursor<&'a [u8]>),
+    BigEndian(io::Cursor<&'a [u8]>),
+}
+
+impl<'a> Body<'a> {
+    fn read(&mut self, ty: ScalarType) -> io::Result<f64> {
+        match *self {
+            Body::Ascii(ref mut tokens, ref mut remaining) => {
+                *remaining = remaining.saturating_sub(1);
+                tokens
+                    .next()
+                    .ok_or_else(|| invalid_data("unexpected end of file"))?
+                    .parse::<f64>()
+                    .map_err(|_| invalid_data("malformed number"))
+            }
+            Body::LittleEndian(ref mut cursor) => read_binary::<LittleEndian>(cursor, ty),
+            Body::BigEndian(ref mut cursor) => read_binary::<BigEndian>(cursor, ty),
+        }
+    }
+
+    /// Number of the values of the type, which are left in the body
+    fn remaining(&self, ty: ScalarType) -> usize {
+        match *self {
+            Body::Ascii(_, remaining) => remaining,
+            Body::LittleEndian(ref cursor) | Body::BigEndian(ref cursor) => {
+                let left = cursor.get_ref().len() as u64 - cursor.position();
+                (left / scalar_size(ty) as u64) as usize
+            }
+        }
+    }
+
+    /// Reads all values of a single element. Scalar properties produce one value,
+    /// list properties produce as many values as the list contains.
+    fn read_element(&mut self, element: &Element) -> io::Result<Vec<Vec<f64>>> {
+        let mut values = Vec::with_capacity(element.properties.len());
+        for property in &element.properties {
+            match property.ty {
+                PropertyType::Scalar(ty) => values.push(vec![self.read(ty)?]),
+                PropertyType::List(count_ty, item_ty) => {
+                    // the count comes from the file, so it is checked before it is used
+                    let count = self.read(count_ty)?;
+                    let remaining = self.remaining(item_ty) as f64;
+                    if count < 0.0 || count.fract() != 0.0 || count > remaining {
+                        return Err(invalid_data("list is longer than the rest of the file"));
+                    }
+                    let count = count as usize;
+                    let mut items = Vec::with_capacity(cmp::min(count, 16));
+                    for _ in 0..count {
+                        items.push(self.read(item_ty)?);
+                    }
+                    values.push(items);
+                }
+            }
+        }
+        Ok(values)
+    }
+}
+
+pub fn load(
+    path: &Path,
+) -> io::Result<(Mesh, Vec<tracers::ty::Material>, Vec<image::RgbaImage>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let (format, elements) = read_header(&mut reader)?;
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut body = match format {
+        Format::Ascii => {
+            let text = str::from_utf8(&data)
+                .map_err(|_| invalid_data("ASCII body is not valid UTF-8"))?;
+            Body::Ascii(text.split_whitespace(), text.split_whitespace().count())
+        }
+        Format::BinaryLittleEndian => Body::LittleEndian(io::Cursor::new(&data[..])),
+        Format::BinaryBigEndian => Body::BigEndian(io::Cursor::new(&data[..])),
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(
+                &mut body,
+                element,
+                &mut positions,
+                &mut normals,
+                &mut texcoords,
+                &mut colors,
+            )?,
+            "face" => read_faces(&mut body, element, &mut indices)?,
+            _ => for _ in 0..element.count {
+                body.read_element(element)?;
+            },
+        }
+    }
+
+    let vertex_count = positions.len() / 3;
+    if indices.iter().any(|&i| i as usize >= vertex_count) {
+        return Err(invalid_data("face references vertex out of range"));
+    }
+    // missing normals and texture coordinates are generated by the caller
+
+    // models with vertex colors are painted with them
+    let mut materials = Vec::new();
+    if !colors.is_empty() {
+        materials.push(vertex_color_material());
+    }
+
+    let mut mesh = Mesh::new();
+    mesh.models.push(Model {
+        indices_start: 0,
+        indices_end: indices.len() as u32 / 3,
+        material_idx: if materials.is_empty() { NO_MATERIAL } else { 0 },
+    });
+    mesh.positions = positions;
+    mesh.indices = indices;
+    mesh.normals = normals;
+    mesh.texcoords = texcoords;
+    mesh.colors = colors;
+    Ok((mesh, materials, Vec::new()))
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid_data("missing 'ply' magic number"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("unexpected end of header"));
+        }
+        let tokens: Vec<_> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            &["end_header"] => break,
+            &["format", "ascii", _] => format = Some(Format::Ascii),
+            &["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            &["format", "binary_big_endian", _] => format = Some(Format::BinaryBigEndian),
+            &["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("malformed element count"))?,
+                properties: Vec::new(),
+            }),
+            &["property", "list", count_ty, item_ty, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    ty: PropertyType::List(scalar_type(count_ty)?, scalar_type(item_ty)?),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property without element"))?
+                    .properties
+                    .push(property);
+            }
+            &["property", ty, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    ty: PropertyType::Scalar(scalar_type(ty)?),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("property without element"))?
+                    .properties
+                    .push(property);
+            }
+            _ => (), // comments, obj_info and empty lines
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("missing format line"))?;
+    Ok((format, elements))
+}
+
+fn read_vertices(
+    body: &mut Body,
+    element: &Element,
+    positions: &mut Vec<f32>,
+    normals: &mut Vec<f32>,
+    texcoords: &mut Vec<f32>,
+    colors: &mut Vec<f32>,
+) -> io::Result<()> {
+    let find = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    };
+    let position_idx = match (find(&["x"]), find(&["y"]), find(&["z"])) {
+        (Some(x), Some(y), Some(z)) => [x, y, z],
+        _ => return Err(invalid_data("vertex element without x, y and z properties")),
+    };
+    let normal_idx = match (find(&["nx"]), find(&["ny"]), find(&["nz"])) {
+        (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+        _ => None,
+    };
+    let texcoord_idx = match (
+        find(&["u", "s", "texture_u", "texture_s"]),
+        find(&["v", "t", "texture_v", "texture_t"]),
+    ) {
+        (Some(u), Some(v)) => Some([u, v]),
+        _ => None,
+    };
+    let color_idx = match (
+        find(&["red", "r", "diffuse_red"]),
+        find(&["green", "g", "diffuse_green"]),
+        find(&["blue", "b", "diffuse_blue"]),
+    ) {
+        (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+        _ => None,
+    };
+    // integer colors are stored in [0, 255] range, floating point ones in [0, 1]
+    let color_scale = match color_idx.map(|idx| element.properties[idx[0]].ty) {
+        Some(PropertyType::Scalar(ScalarType::Float))
+        | Some(PropertyType::Scalar(ScalarType::Double)) => 1.0,
+        _ => 1.0 / 255.0,
+    };
+
+    for _ in 0..element.count {
+        let values = body.read_element(element)?;
+        let value = |idx: usize| values[idx].first().cloned().unwrap_or(0.0) as f32;
+        positions.extend(position_idx.iter().map(|&idx| value(idx)));
+        if let Some(normal_idx) = normal_idx {
+            normals.extend(normal_idx.iter().map(|&idx| value(idx)));
+        }
+        if let Some(texcoord_idx) = texcoord_idx {
+            texcoords.extend(texcoord_idx.iter().map(|&idx| value(idx)));
+        }
+        if let Some(color_idx) = color_idx {
+            colors.extend(color_idx.iter().map(|&idx| value(idx) * color_scale));
+        }
+    }
+    Ok(())
+}
+
+fn read_faces(body: &mut Body, element: &Element, indices: &mut Vec<u32>) -> io::Result<()> {
+    let indices_idx = element
+        .properties
+        .iter()
+        .position(|p| p.name == "vertex_indices" || p.name == "vertex_index")
+        .ok_or_else(|| invalid_data("face element without vertex_indices property"))?;
+
+    for _ in 0..element.count {
+        let values = body.read_element(element)?;
+        let polygon = &values[indices_idx];
+        // polygons are triangulated as fans around the first vertex
+        for i in 2..polygon.len() {
+            indices.push(polygon[0] as u32);
+            indices.push(polygon[i - 1] as u32);
+            indices.push(polygon[i] as u32);
+        }
+    }
+    Ok(())
+}
+
+fn read_binary<B: ByteOrder>(cursor: &mut io::Cursor<&[u8]>, ty: ScalarType) -> io::Result<f64> {
+    Ok(match ty {
+        ScalarType::Char => cursor.read_i8()? as f64,
+        ScalarType::UChar => cursor.read_u8()? as f64,
+        ScalarType::Short => cursor.read_i16::<B>()? as f64,
+        ScalarType::UShort => cursor.read_u16::<B>()? as f64,
+        ScalarType::Int => cursor.read_i32::<B>()? as f64,
+        ScalarType::UInt => cursor.read_u32::<B>()? as f64,
+        ScalarType::Float => cursor.read_f32::<B>()? as f64,
+        ScalarType::Double => cursor.read_f64::<B>()?,
+    })
+}
+
+fn scalar_size(ty: ScalarType) -> usize {
+    match ty {
+        ScalarType::Char | ScalarType::UChar => 1,
+        ScalarType::Short | ScalarType::UShort => 2,
+        ScalarType::Int | ScalarType::UInt | ScalarType::Float => 4,
+        ScalarType::Double => 8,
+    }
+}
+
+fn scalar_type(name: &str) -> io::Result<ScalarType> {
+    match name {
+        "char" | "int8" => Ok(ScalarType::Char),
+        "uchar" | "uint8" => Ok(ScalarType::UChar),
+        "short" | "int16" => Ok(ScalarType::Short),
+        "ushort" | "uint16" => Ok(ScalarType::UShort),
+        "int" | "int32" => Ok(ScalarType::Int),
+        "uint" | "uint32" => Ok(ScalarType::UInt),
+        "float" | "float32" => Ok(ScalarType::Float),
+        "double" | "float64" => Ok(ScalarType::Double),
+        _ => Err(invalid_data(&format!("unknown property type '{}'", name))),
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,165 @@
+extern crate cgmath;
+
+use super::description::{PrimitiveDescription, Shape};
+
+use cgmath::InnerSpace;
+
+use tracers;
+
+/// Analytic primitives of the scene, laid out the same way as the GPU buffer.
+/// Bounded primitives are inserted into the grid, so they are stored first,
+/// unbounded planes are stored after them and tested against every ray.
+pub struct Primitives {
+    pub primitives: Vec<tracers::ty::Primitive>,
+    /// Bounds of every bounded primitive in the object space
+    pub bounds: Vec<([f32; 3], [f32; 3])>,
+    /// Translation from the world space to the object space
+    pub offset: cgmath::Vector3<f32>,
+}
+
+impl Primitives {
+    /// Bounded primitives are placed along X axis starting at `layout_cursor`, after the
+    /// models in the object space. Planes stay in the world space, they are not in the grid.
+    /// Every primitive gets its own material, which is appended to `materials`.
+    pub fn new(
+        descriptions: &[PrimitiveDescription],
+        layout_cursor: f32,
+        materials: &mut Vec<tracers::ty::Material>,
+    ) -> Primitives {
+        let mut bounded = Vec::new();
+        let mut planes = Vec::new();
+        for description in descriptions {
+            let primitive = match to_primitive(&description.shape) {
+                Some(primitive) => primitive,
+                None => {
+                    println!("warning: skipping degenerate {:?}", description.shape);
+                    continue;
+                }
+            };
+            let primitive = tracers::ty::Primitive {
+                material_idx: materials.len() as u32,
+                ..primitive
+            };
+            materials.push(tracers::ty::Material {
+                specular: description.specular,
+                shininess: description.shininess,
+                ..super::default_material(description.color)
+            });
+            if primitive.kind == PRIMITIVE_PLANE {
+                planes.push(primitive);
+            } else {
+                bounded.push(primitive);
+            }
+        }
+
+        let world_bounds: Vec<_> = bounded.iter().map(primitive_bounds).collect();
+        let min_x = world_bounds
+            .iter()
+            .fold(::std::f32::INFINITY, |min, b| min.min((b.0)[0]));
+        let offset = if world_bounds.is_empty() {
+            cgmath::Vector3::new(0.0, 0.0, 0.0)
+        } else {
+            cgmath::Vector3::new(layout_cursor - min_x, 0.0, 0.0)
+        };
+        for primitive in &mut bounded {
+            primitive.center[0] += offset.x;
+        }
+        let bounds = bounded.iter().map(primitive_bounds).collect();
+
+        bounded.extend(planes);
+        Primitives {
+            primitives: bounded,
+            bounds,
+            offset,
+        }
+    }
+
+    /// Bounds of all bounded primitives in the object space
+    pub fn bbox(&self) -> Option<([f32; 3], [f32; 3])> {
+        if self.bounds.is_empty() {
+            return None;
+        }
+        let mut min = [::std::f32::INFINITY; 3];
+        let mut max = [::std::f32::NEG_INFINITY; 3];
+        for &(primitive_min, primitive_max) in &self.bounds {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(primitive_min[axis]);
+                max[axis] = max[axis].max(primitive_max[axis]);
+            }
+        }
+        Some((min, max))
+    }
+}
+
+/// Primitive without material, `None` if it has zero size or normal
+fn to_primitive(shape: &Shape) -> Option<tracers::ty::Primitive> {
+    let primitive = |kind, center, normal, radius, half_size| tracers::ty::Primitive {
+        center,
+        kind,
+        normal,
+        radius,
+        half_size,
+        material_idx: 0,
+    };
+    let normalize = |normal: [f32; 3]| -> Option<[f32; 3]> {
+        let normal = cgmath::Vector3::from(normal);
+        if normal.magnitude2() > 0.0 {
+            Some(normal.normalize().into())
+        } else {
+            None
+        }
+    };
+    match *shape {
+        Shape::Sphere { center, radius } if radius > 0.0 => Some(primitive(
+            PRIMITIVE_SPHERE,
+            center,
+            [0.0; 3],
+            radius,
+            [0.0; 3],
+        )),
+        Shape::Plane { point, normal } => normalize(normal)
+            .map(|normal| primitive(PRIMITIVE_PLANE, point, normal, 0.0, [0.0; 3])),
+        Shape::Box { min, max } => {
+            let mut center = [0.0; 3];
+            let mut half_size = [0.0; 3];
+            for axis in 0..3 {
+                center[axis] = 0.5 * (min[axis] + max[axis]);
+                half_size[axis] = 0.5 * (max[axis] - min[axis]).abs();
+            }
+            Some(primitive(PRIMITIVE_BOX, center, [0.0; 3], 0.0, half_size))
+        }
+        Shape::Disk {
+            center,
+            normal,
+            radius,
+        } if radius > 0.0 =>
+        {
+            normalize(normal)
+                .map(|normal| primitive(PRIMITIVE_DISK, center, normal, radius, [0.0; 3]))
+        }
+        _ => None,
+    }
+}
+
+fn primitive_bounds(primitive: &tracers::ty::Primitive) -> ([f32; 3], [f32; 3]) {
+    let mut extent = [0.0; 3];
+    for axis in 0..3 {
+        extent[axis] = match primitive.kind {
+            PRIMITIVE_SPHERE => primitive.radius,
+            PRIMITIVE_BOX => primitive.half_size[axis],
+            // projection of the disk onto the axis
+            _ => primitive.radius * (1.0 - primitive.normal[axis].powi(2)).max(0.0).sqrt(),
+        };
+    }
+    let c = primitive.center;
+    (
+        [c[0] - extent[0], c[1] - extent[1], c[2] - extent[2]],
+        [c[0] + extent[0], c[1] + extent[1], c[2] + extent[2]],
+    )
+}
+
+// must match the constants in tracer.comp.tera
+const PRIMITIVE_SPHERE: u32 = 0;
+const PRIMITIVE_PLANE: u32 = 1;
+const PRIMITIVE_BOX: u32 = 2;
+const PRIMITIVE_DISK: u32 = 3;
@@ -0,0 +1,148 @@
+extern crate byteorder;
+extern crate cgmath;
+extern crate image;
+
+use self::byteorder::{LittleEndian, ReadBytesExt};
+use cgmath::InnerSpace;
+
+use super::{Mesh, Model, NO_MATERIAL};
+use tracers;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+struct Triangle {
+    normal: [f32; 3],
+    vertices: [[f32; 3]; 3],
+}
+
+pub fn load(
+    path: &Path,
+) -> io::Result<(Mesh, Vec<tracers::ty::Material>, Vec<image::RgbaImage>)> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    // ASCII files start with "solid", but so do some binary ones,
+    // so the size written in the binary header is checked first
+    let triangles = if is_binary(&data) {
+        read_binary(&data)?
+    } else {
+        read_ascii(&data)?
+    };
+
+    // STL doesn't share vertices between faces, so every face
+    // gets its own vertices with the face normal
+    let mut mesh = Mesh::new();
+    for triangle in &triangles {
+        let normal = face_normal(triangle);
+        for vertex in &triangle.vertices {
+            mesh.indices.push(mesh.positions.len() as u32 / 3);
+            mesh.positions.extend_from_slice(vertex);
+            mesh.normals.extend_from_slice(&normal);
+        }
+    }
+    mesh.models.push(Model {
+        indices_start: 0,
+        indices_end: triangles.len() as u32,
+        material_idx: NO_MATERIAL,
+    });
+    Ok((mesh, Vec::new(), Vec::new()))
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    if data.len() < HEADER_SIZE + 4 {
+        return false;
+    }
+    let count = (&data[HEADER_SIZE..]).read_u32::<LittleEndian>().unwrap() as usize;
+    data.len() == HEADER_SIZE + 4 + count * TRIANGLE_SIZE
+}
+
+fn read_binary(data: &[u8]) -> io::Result<Vec<Triangle>> {
+    let mut reader = &data[HEADER_SIZE..];
+    let count = reader.read_u32::<LittleEndian>()? as usize;
+    let mut triangles = Vec::with_capacity(count);
+    for _ in 0..count {
+        let normal = read_vec3(&mut reader)?;
+        let vertices = [
+            read_vec3(&mut reader)?,
+            read_vec3(&mut reader)?,
+            read_vec3(&mut reader)?,
+        ];
+        // attribute byte count, which is used only by some exporters for colors
+        reader.read_u16::<LittleEndian>()?;
+        triangles.push(Triangle { normal, vertices });
+    }
+    Ok(triangles)
+}
+
+fn read_vec3(reader: &mut &[u8]) -> io::Result<[f32; 3]> {
+    Ok([
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+        reader.read_f32::<LittleEndian>()?,
+    ])
+}
+
+fn read_ascii(data: &[u8]) -> io::Result<Vec<Triangle>> {
+    let text = str::from_utf8(data).map_err(|_| invalid_data("ASCII STL is not valid UTF-8"))?;
+    let mut tokens = text.split_whitespace();
+    let mut triangles = Vec::new();
+    let mut normal = [0.0; 3];
+    let mut polygon = Vec::with_capacity(3);
+    while let Some(token) = tokens.next() {
+        match token {
+            "normal" => normal = next_vec3(&mut tokens)?,
+            "vertex" => polygon.push(next_vec3(&mut tokens)?),
+            "endfacet" => {
+                // facets are triangles by the specification, but be tolerant to polygons
+                for i in 2..polygon.len() {
+                    triangles.push(Triangle {
+                        normal,
+                        vertices: [polygon[0], polygon[i - 1], polygon[i]],
+                    });
+                }
+                polygon.clear();
+            }
+            _ => (),
+        }
+    }
+    Ok(triangles)
+}
+
+fn next_vec3(tokens: &mut str::SplitWhitespace) -> io::Result<[f32; 3]> {
+    let mut v = [0.0; 3];
+    for c in v.iter_mut() {
+        *c = tokens
+            .next()
+            .ok_or_else(|| invalid_data("unexpected end of file"))?
+            .parse()
+            .map_err(|_| invalid_data("malformed number"))?;
+    }
+    Ok(v)
+}
+
+/// Uses the stored normal unless exporter left it zero, which is common
+fn face_normal(triangle: &Triangle) -> [f32; 3] {
+    let normal = cgmath::Vector3::from(triangle.normal);
+    if normal.magnitude2() > 0.0 {
+        return normal.normalize().into();
+    }
+    let v0 = cgmath::Vector3::from(triangle.vertices[0]);
+    let v1 = cgmath::Vector3::from(triangle.vertices[1]);
+    let v2 = cgmath::Vector3::from(triangle.vertices[2]);
+    let normal = (v1 - v0).cross(v2 - v0);
+    if normal.magnitude2() > 0.0 {
+        normal.normalize().into()
+    } else {
+        [0.0; 3]
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
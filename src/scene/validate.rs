@@ -0,0 +1,241 @@
+use super::Mesh;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Problems found in the model. Triangles which can't be rendered at all are
+/// always dropped, the rest is fixed only if the repair is requested.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Report {
+    /// Triangles referencing vertices which don't exist
+    pub out_of_range_triangles: u32,
+    /// Triangles with NaN or infinite coordinates
+    pub non_finite_triangles: u32,
+    /// Triangles with zero area
+    pub degenerate_triangles: u32,
+    /// Vertices with NaN or infinite normals, texture coordinates or colors.
+    /// Normals and texture coordinates are generated again, colors are dropped.
+    pub non_finite_attributes: u32,
+    /// Vertices with the same attributes as another vertex
+    pub duplicate_vertices: u32,
+    /// Vertices not referenced by any triangle
+    pub unused_vertices: u32,
+}
+
+impl Report {
+    pub fn add(&mut self, other: &Report) {
+        self.out_of_range_triangles += other.out_of_range_triangles;
+        self.non_finite_triangles += other.non_finite_triangles;
+        self.degenerate_triangles += other.degenerate_triangles;
+        self.non_finite_attributes += other.non_finite_attributes;
+        self.duplicate_vertices += other.duplicate_vertices;
+        self.unused_vertices += other.unused_vertices;
+    }
+
+    pub fn is_clean(&self) -> bool {
+        *self == Report::default()
+    }
+
+    /// Whether there are problems which are fixed only by the repair
+    pub fn is_repairable(&self) -> bool {
+        self.degenerate_triangles > 0 || self.duplicate_vertices > 0 || self.unused_vertices > 0
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problems = [
+            (self.out_of_range_triangles, "triangles with out of range indices"),
+            (self.non_finite_triangles, "triangles with non-finite coordinates"),
+            (self.degenerate_triangles, "degenerate triangles"),
+            (self.non_finite_attributes, "vertices with non-finite attributes"),
+            (self.duplicate_vertices, "duplicate vertices"),
+            (self.unused_vertices, "unused vertices"),
+        ];
+        let mut first = true;
+        for &(count, problem) in problems.iter().filter(|&&(count, _)| count > 0) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {}", count, problem)?;
+            first = false;
+        }
+        if first {
+            write!(f, "no problems")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Problem {
+    OutOfRange,
+    NonFinite,
+    Degenerate,
+}
+
+/// Checks the part of the model before its missing attributes are generated.
+/// Triangles referencing missing or non-finite vertices hang the GPU or produce
+/// black pixels, so they are dropped regardless of `repair`.
+pub fn validate(mesh: &mut Mesh, repair: bool) -> Report {
+    let mut report = Report::default();
+    let vertex_count = mesh.positions.len() / 3;
+    report.non_finite_attributes = drop_non_finite(&mut mesh.normals, 3, vertex_count)
+        + drop_non_finite(&mut mesh.texcoords, 2, vertex_count)
+        + drop_non_finite(&mut mesh.colors, 3, vertex_count);
+
+    let triangle_count = mesh.indices.len() / 3;
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for model in &mut mesh.models {
+        let end = cmp::min(model.indices_end as usize, triangle_count);
+        let start = cmp::min(model.indices_start as usize, end);
+        model.indices_start = indices.len() as u32 / 3;
+        for triangle in mesh.indices[3 * start..3 * end].chunks(3) {
+            let keep = match check_triangle(&mesh.positions, triangle) {
+                None => true,
+                Some(Problem::OutOfRange) => {
+                    report.out_of_range_triangles += 1;
+                    false
+                }
+                Some(Problem::NonFinite) => {
+                    report.non_finite_triangles += 1;
+                    false
+                }
+                Some(Problem::Degenerate) => {
+                    report.degenerate_triangles += 1;
+                    !repair
+                }
+            };
+            if keep {
+                indices.extend_from_slice(triangle);
+            }
+        }
+        model.indices_end = indices.len() as u32 / 3;
+    }
+
+    // vertices are compared by all of their attributes, so welding
+    // doesn't remove seams of normals or texture coordinates
+    let has_normals = mesh.normals.len() == 3 * vertex_count;
+    let has_texcoords = mesh.texcoords.len() == 2 * vertex_count;
+    let has_colors = mesh.colors.len() == 3 * vertex_count;
+    let mut welded = HashMap::new();
+    let mut originals = Vec::new();
+    let mut remap = vec![None; vertex_count];
+    let mut used_count = 0;
+    for &i in &indices {
+        let i = i as usize;
+        if remap[i].is_some() {
+            continue;
+        }
+        used_count += 1;
+        let key = (
+            bits(&mesh.positions[3 * i..3 * i + 3]),
+            if has_normals {
+                bits(&mesh.normals[3 * i..3 * i + 3])
+            } else {
+                Vec::new()
+            },
+            if has_texcoords {
+                bits(&mesh.texcoords[2 * i..2 * i + 2])
+            } else {
+                Vec::new()
+            },
+            if has_colors {
+                bits(&mesh.colors[3 * i..3 * i + 3])
+            } else {
+                Vec::new()
+            },
+        );
+        let next_id = originals.len() as u32;
+        let id = *welded.entry(key).or_insert(next_id);
+        if id == next_id {
+            originals.push(i);
+        }
+        remap[i] = Some(id);
+    }
+    report.unused_vertices = (vertex_count - used_count) as u32;
+    report.duplicate_vertices = (used_count - originals.len()) as u32;
+
+    if repair && (report.unused_vertices > 0 || report.duplicate_vertices > 0) {
+        mesh.positions = gather(&mesh.positions, 3, &originals);
+        if has_normals {
+            mesh.normals = gather(&mesh.normals, 3, &originals);
+        }
+        if has_texcoords {
+            mesh.texcoords = gather(&mesh.texcoords, 2, &originals);
+        }
+        if has_colors {
+            mesh.colors = gather(&mesh.colors, 3, &originals);
+        }
+        for i in &mut indices {
+            *i = remap[*i as usize].unwrap();
+        }
+    }
+    mesh.indices = indices;
+    report
+}
+
+fn check_triangle(positions: &[f32], triangle: &[u32]) -> Option<Problem> {
+    let vertex_count = positions.len() / 3;
+    if triangle.iter().any(|&i| i as usize >= vertex_count) {
+        return Some(Problem::OutOfRange);
+    }
+    if triangle
+        .iter()
+        .any(|&i| vertex(positions, i).iter().any(|c| !c.is_finite()))
+    {
+        return Some(Problem::NonFinite);
+    }
+    if triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[0] == triangle[2] {
+        return Some(Problem::Degenerate);
+    }
+    let v0 = vertex(positions, triangle[0]);
+    let v1 = vertex(positions, triangle[1]);
+    let v2 = vertex(positions, triangle[2]);
+    let e1 = [v1[0] - v0[0], v1[1] - v0[1], v1[2] - v0[2]];
+    let e2 = [v2[0] - v0[0], v2[1] - v0[1], v2[2] - v0[2]];
+    let cross = [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ];
+    if cross.iter().all(|&c| c == 0.0) {
+        return Some(Problem::Degenerate);
+    }
+    None
+}
+
+fn vertex(positions: &[f32], i: u32) -> &[f32] {
+    let i = i as usize;
+    &positions[3 * i..3 * i + 3]
+}
+
+/// Drops the attribute if any of its values is not finite, so it's generated again.
+/// Returns the number of vertices with such values.
+fn drop_non_finite(values: &mut Vec<f32>, size: usize, vertex_count: usize) -> u32 {
+    if values.len() != size * vertex_count {
+        // incomplete attributes are generated anyway
+        return 0;
+    }
+    let count = values
+        .chunks(size)
+        .filter(|v| v.iter().any(|c| !c.is_finite()))
+        .count();
+    if count > 0 {
+        values.clear();
+    }
+    count as u32
+}
+
+fn bits(values: &[f32]) -> Vec<u32> {
+    values.iter().map(|v| v.to_bits()).collect()
+}
+
+fn gather(values: &[f32], size: usize, originals: &[usize]) -> Vec<f32> {
+    let mut result = Vec::with_capacity(size * originals.len());
+    for &i in originals {
+        result.extend_from_slice(&values[size * i..size * i + size]);
+    }
+    result
+}
@@ -0,0 +1,92 @@
+use super::{load_scene, LoadedScene, SceneDescription, SceneError};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often modification times of the files are checked
+const POLL_INTERVAL_MS: u64 = 500;
+
+/// Watches files of the scene and reloads it in the background when they change
+pub struct SceneWatcher {
+    description: SceneDescription,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_poll: Instant,
+    loading: Option<mpsc::Receiver<Result<LoadedScene, SceneError>>>,
+}
+
+impl SceneWatcher {
+    pub fn new(description: SceneDescription, files: &[PathBuf]) -> SceneWatcher {
+        SceneWatcher {
+            description,
+            files: files.iter().map(|f| (f.clone(), modified(f))).collect(),
+            last_poll: Instant::now(),
+            loading: None,
+        }
+    }
+
+    /// Returns the reloaded scene, when the background loading is finished.
+    /// Never blocks, so it can be called every frame.
+    pub fn poll(&mut self) -> Option<Result<LoadedScene, SceneError>> {
+        if let Some(result) = self.finished_loading() {
+            if let Ok(ref scene) = result {
+                self.files = scene.files.iter().map(|f| (f.clone(), modified(f))).collect();
+            }
+            return Some(result);
+        }
+        if self.loading.is_some()
+            || self.last_poll.elapsed() < Duration::from_millis(POLL_INTERVAL_MS)
+        {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = false;
+        for &mut (ref path, ref mut last_modified) in &mut self.files {
+            let current = modified(path);
+            if current != *last_modified {
+                // remember the change right away, so the failed reload
+                // of the half-written file is not repeated every poll
+                *last_modified = current;
+                changed = true;
+            }
+        }
+        if changed {
+            println!("scene files changed, reloading");
+            let (sender, receiver) = mpsc::channel();
+            let description = self.description.clone();
+            thread::spawn(move || {
+                // receiver is gone only if the viewer is closed
+                let _ = sender.send(load_scene(&description));
+            });
+            self.loading = Some(receiver);
+        }
+        None
+    }
+
+    fn finished_loading(&mut self) -> Option<Result<LoadedScene, SceneError>> {
+        let received = match self.loading {
+            Some(ref receiver) => receiver.try_recv(),
+            None => return None,
+        };
+        match received {
+            Ok(result) => {
+                self.loading = None;
+                Some(result)
+            }
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                println!("failed to reload the scene: loader thread panicked");
+                self.loading = None;
+                None
+            }
+        }
+    }
+}
+
+/// Missing files are not an error here, they may be in the middle of saving
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -1,5 +1,5 @@
 mod raycasting;
 mod tracer;
 
-pub use self::raycasting::{RaycastingShader, ty};
-pub use self::tracer::{Tracer, TracingShader};
+pub use self::raycasting::{traversals, RaycastingShader, ty};
+pub use self::tracer::{Tracer, TracerPipeline, TracingShader};
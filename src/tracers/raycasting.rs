@@ -1,42 +1,75 @@
 extern crate vulkano;
 
 use control::Camera;
-use grid::Grid;
-use tracers::TracingShader;
+use tracers::{TracerPipeline, TracingShader};
 
 use std::sync::Arc;
 
-mod shader {
-    #![allow(dead_code)]
-    #[derive(VulkanoShader)]
-    #[ty = "compute"]
-    #[path = "target/shaders/raycasting.comp"]
-    struct Dummy;
+/// Declares `new_pipeline`, which loads the tracer with the traversal by its name.
+/// Types shared by all the traversals are taken from the first one.
+macro_rules! traversal_pipelines {
+    ($first:ident $(, $traversal:ident)*) => {
+        pub use self::traversals::$first::ty;
+
+        fn new_pipeline(device: Arc<vulkano::device::Device>, traversal: &str) -> TracerPipeline {
+            if traversal == stringify!($first) {
+                load_pipeline!(device, $first)
+            }
+            $(else if traversal == stringify!($traversal) {
+                load_pipeline!(device, $traversal)
+            })*
+            else {
+                panic!("no traversal {} in shaders/accel", traversal)
+            }
+        }
+    };
+}
+
+macro_rules! load_pipeline {
+    ($device:expr, $traversal:ident) => {{
+        let shader = traversals::$traversal::Shader::load($device.clone())
+            .expect("failed to create shader module");
+        let pipeline = Arc::new(
+            vulkano::pipeline::ComputePipeline::new(
+                $device.clone(),
+                &shader.main_entry_point(),
+                &(),
+            ).expect("failed to create compute pipeline"),
+        );
+        TracerPipeline {
+            pipeline: pipeline.clone(),
+            layout: pipeline,
+        }
+    }};
 }
 
+// shader modules rendered by build.rs for every snippet in shaders/accel
+include!(concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/target/shaders/raycasting_traversals.rs"
+));
+
 pub struct RaycastingShader {}
 
 impl TracingShader for RaycastingShader {
     type Uniform = ty::Uniform;
-    type Shader = self::shader::Shader;
-    type Layout = self::shader::Layout;
 
-    fn load_shader(self, device: Arc<vulkano::device::Device>) -> Self::Shader {
-        Self::Shader::load(device.clone()).expect("failed to create shader module")
+    fn load_pipeline(
+        self,
+        device: Arc<vulkano::device::Device>,
+        traversal: &str,
+    ) -> TracerPipeline {
+        new_pipeline(device, traversal)
     }
-    fn new_uniform(self, camera: &Camera, grid: &Grid) -> Self::Uniform {
-        Self::Uniform::new(camera, grid)
+    fn new_uniform(self, camera: &Camera) -> Self::Uniform {
+        Self::Uniform::new(camera)
     }
 }
 
-pub use self::shader::{ty, Layout, Shader};
-
 impl ty::Uniform {
-    pub fn new(camera: &Camera, grid: &Grid) -> ty::Uniform {
+    pub fn new(camera: &Camera) -> ty::Uniform {
         ty::Uniform {
             camera: ty::Camera::new(&camera),
-            grid: ty::Grid::new(&grid),
-            _dummy0: [0; 4],
         }
     }
 }
@@ -55,17 +88,3 @@ impl ty::Camera {
         }
     }
 }
-
-impl ty::Grid {
-    fn new(grid: &Grid) -> ty::Grid {
-        ty::Grid {
-            minimum_cell: grid.bbox.min.position,
-            maximum_cell: grid.bbox.max.position,
-            resolution: grid.resolution,
-            cell_size: grid.cell_size,
-            _dummy0: [0; 4],
-            _dummy1: [0; 4],
-            _dummy2: [0; 4],
-        }
-    }
-}
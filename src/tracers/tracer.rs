@@ -3,54 +3,56 @@ use vulkano::descriptor::descriptor_set;
 
 use super::raycasting;
 
+use accel::AccelerationStructure;
 use control::Camera;
-use grid::Grid;
 use scene;
 
 use std::sync::Arc;
 
+/// Set of the acceleration structure, which is bound by the snippets in shaders/accel
+const STRUCTURE_SET: usize = 2;
+
 pub trait TracingShader {
-    type Layout;
     type Uniform;
-    type Shader;
 
-    fn load_shader(self, device: Arc<vulkano::device::Device>) -> Self::Shader;
-    fn new_uniform(self, camera: &Camera, grid: &Grid) -> Self::Uniform;
+    /// Pipeline of the tracer with the traversal of the snippet `traversal` from shaders/accel
+    fn load_pipeline(
+        self,
+        device: Arc<vulkano::device::Device>,
+        traversal: &str,
+    ) -> TracerPipeline;
+    fn new_uniform(self, camera: &Camera) -> Self::Uniform;
+}
+
+/// Compute pipeline of the tracer, the layout is the same pipeline, which the descriptor
+/// sets are built for
+pub struct TracerPipeline {
+    pub pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    pub layout: Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
 }
 
 pub struct Tracer<TS: TracingShader> {
-    pipeline: Arc<vulkano::pipeline::ComputePipelineAbstract + Send + Sync>,
+    pipeline: TracerPipeline,
     uniform_buffer_pool: vulkano::buffer::CpuBufferPool<TS::Uniform>,
     ds_pool: descriptor_set::FixedSizeDescriptorSetsPool<
-        Arc<
-            vulkano::pipeline::ComputePipeline<
-                vulkano::descriptor::pipeline_layout::PipelineLayout<TS::Layout>,
-            >,
-        >,
+        Arc<vulkano::descriptor::pipeline_layout::PipelineLayoutAbstract + Send + Sync>,
     >,
     model_set: Arc<vulkano::descriptor::DescriptorSet + Send + Sync>,
 }
 
-impl<TS: TracingShader<Uniform = raycasting::ty::Uniform, Shader = raycasting::Shader, Layout = raycasting::Layout>>
-    Tracer<TS>
-{
+impl<TS: TracingShader<Uniform = raycasting::ty::Uniform>> Tracer<TS> {
+    /// The tracer can render only the acceleration structures with the traversal `traversal`
     pub fn new(
         device: Arc<vulkano::device::Device>,
         scene_buffers: &scene::ModelBuffers,
+        traversal: &str,
         traing_shader: TS,
     ) -> Result<Tracer<TS>, descriptor_set::PersistentDescriptorSetError> {
-        let shader = traing_shader.load_shader(device.clone());
-        let pipeline = Arc::new(
-            vulkano::pipeline::ComputePipeline::new(
-                device.clone(),
-                &shader.main_entry_point(),
-                &(),
-            ).expect("failed to create compute pipeline"),
-        );
+        let pipeline = traing_shader.load_pipeline(device.clone(), traversal);
         let uniform_buffer_pool = vulkano::buffer::CpuBufferPool::uniform_buffer(device.clone());
-        let ds_pool = descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.clone(), 0);
+        let ds_pool = descriptor_set::FixedSizeDescriptorSetsPool::new(pipeline.layout.clone(), 0);
         let model_set = scene_buffers
-            .build_descriptor_set(device.clone(), pipeline.clone(), 1)
+            .build_descriptor_set(pipeline.layout.clone(), 1)
             .expect("failed to build scene descriptor set");
 
         Ok(Tracer {
@@ -61,17 +63,26 @@ impl<TS: TracingShader<Uniform = raycasting::ty::Uniform, Shader = raycasting::S
         })
     }
 
+    /// Replaces the scene, which is rendered, e.g. when it is reloaded
+    pub fn set_scene(
+        &mut self,
+        scene_buffers: &scene::ModelBuffers,
+    ) -> Result<(), descriptor_set::PersistentDescriptorSetError> {
+        self.model_set = scene_buffers.build_descriptor_set(self.pipeline.layout.clone(), 1)?;
+        Ok(())
+    }
+
     pub fn render(
         &mut self,
         builder: vulkano::command_buffer::AutoCommandBufferBuilder,
         texture: Arc<vulkano::image::StorageImage<vulkano::format::R8G8B8A8Unorm>>,
         statistics: Arc<vulkano::buffer::BufferAccess + Send + Sync>,
         camera: &Camera,
-        grid: &Grid,
+        acceleration: &AccelerationStructure,
     ) -> vulkano::command_buffer::AutoCommandBufferBuilder {
         let dimensions = texture.dimensions();
         let uniform_buffer = self.uniform_buffer_pool
-            .next(TS::Uniform::new(&camera, &grid))
+            .next(TS::Uniform::new(&camera))
             .expect("failed to create uniform buffer");
         let ds = self.ds_pool
             .next()
@@ -81,17 +92,16 @@ impl<TS: TracingShader<Uniform = raycasting::ty::Uniform, Shader = raycasting::S
             .unwrap()
             .add_buffer(statistics)
             .unwrap()
-            .add_buffer(grid.cells_buffer.clone())
-            .unwrap()
-            .add_buffer(grid.references_buffer.clone())
-            .unwrap()
             .build()
             .unwrap();
+        let structure_set = acceleration
+            .build_descriptor_set(self.pipeline.layout.clone(), STRUCTURE_SET)
+            .expect("failed to build acceleration structure descriptor set");
         builder
             .dispatch(
                 [dimensions.width() / 16, dimensions.height() / 16, 1],
-                self.pipeline.clone(),
-                (ds, self.model_set.clone()),
+                self.pipeline.pipeline.clone(),
+                (ds, self.model_set.clone(), structure_set),
                 (),
             )
             .unwrap()